@@ -0,0 +1,147 @@
+use crate::detector;
+use crate::library::{self, AnalyzedGame};
+use peppi::ParseError;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+/// Set this to use a different cache directory.
+const CACHE_DIR_VAR: &str = "SLIPNSIGHTS_CACHE";
+
+/// Bump this when `AnalyzedGame` (or anything in it, like `GameInfo`) changes what gets saved, so
+/// entries saved in the old format are redone instead of being read with missing fields.
+const ENTRY_VERSION: u32 = 2;
+
+/// Analysis results saved to disk, one JSON file per replay named after its content hash, so
+/// replays that haven't changed don't get parsed again.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+/// What gets written for each replay.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    version: u32,
+    /// `detector::fingerprint()` when this was analyzed. Entries from any other version or
+    /// settings are out of date and get redone.
+    detector: String,
+    game: AnalyzedGame,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf) -> Cache {
+        Cache { dir }
+    }
+
+    /// `$SLIPNSIGHTS_CACHE` if set, otherwise `slipnsights-rs` in the user's cache directory
+    /// (`$XDG_CACHE_HOME` or `~/.cache`), falling back to `.slipnsights-cache` here.
+    pub fn open_default() -> Cache {
+        let dir = env::var_os(CACHE_DIR_VAR).map(PathBuf::from).unwrap_or_else(|| {
+            env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+                .map_or_else(|| PathBuf::from(".slipnsights-cache"), |dir| dir.join("slipnsights-rs"))
+        });
+        Cache::new(dir)
+    }
+
+    /// Analyzes the replay at `path`, or returns the cached result if the file's contents haven't changed.
+    pub fn analyze(&self, path: &Path) -> Result<AnalyzedGame, ParseError> {
+        let bytes = fs::read(path).map_err(|error| ParseError { pos: None, error })?;
+        let hash = library::hash_bytes(&bytes);
+        if let Some(mut game) = self.get(&hash) {
+            game.path = path.to_path_buf();
+            return Ok(game);
+        }
+
+        let game = library::analyze_replay_from(path, &mut bytes.as_slice())?;
+        // A cache we can't write to just means analyzing again next time.
+        if let Err(e) = self.put(&hash, &game) {
+            eprintln!("Couldn't cache {}: {}", path.display(), e);
+        }
+        Ok(game)
+    }
+
+    fn entry_path(&self, hash: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", hash))
+    }
+
+    fn get(&self, hash: &str) -> Option<AnalyzedGame> {
+        let file = fs::File::open(self.entry_path(hash)).ok()?;
+        let entry: Entry = serde_json::from_reader(io::BufReader::new(file)).ok()?;
+        match entry.version == ENTRY_VERSION && entry.detector == detector::fingerprint() {
+            true => Some(entry.game),
+            _ => None,
+        }
+    }
+
+    fn put(&self, hash: &str, game: &AnalyzedGame) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let entry = Entry {
+            version: ENTRY_VERSION,
+            detector: detector::fingerprint(),
+            game: game.clone(),
+        };
+        let file = fs::File::create(self.entry_path(hash))?;
+        serde_json::to_writer(io::BufWriter::new(file), &entry).map_err(io::Error::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::process;
+
+    fn cache(name: &str) -> Cache {
+        Cache::new(env::temp_dir().join(format!("slipnsights-cache-{}-{}", name, process::id())))
+    }
+
+    fn write_entry(cache: &Cache, hash: &str, version: u32, detector: String) {
+        let entry = Entry { version, detector, game: testing::game(Vec::new(), Vec::new()) };
+        fs::write(cache.entry_path(hash), serde_json::to_vec(&entry).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn reuses_the_result_for_the_same_contents() {
+        let cache = cache("hit");
+        fs::create_dir_all(&cache.dir).unwrap();
+        let (first, second) = (cache.dir.join("first.slp"), cache.dir.join("second.slp"));
+        fs::write(&first, testing::fair_replay(300)).unwrap();
+        fs::write(&second, testing::fair_replay(300)).unwrap();
+
+        let analyzed = cache.analyze(&first).unwrap();
+        let hash = library::hash_bytes(&fs::read(&first).unwrap());
+        assert!(cache.get(&hash).is_some());
+
+        // A copy somewhere else comes from the cache, under its own path.
+        let cached = cache.analyze(&second).unwrap();
+        assert_eq!(cached.path, second);
+        assert_eq!(format!("{:?}", cached.conversions), format!("{:?}", analyzed.conversions));
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn out_of_date_entries_are_misses() {
+        let cache = cache("stale");
+        fs::create_dir_all(&cache.dir).unwrap();
+
+        write_entry(&cache, "current", ENTRY_VERSION, detector::fingerprint());
+        assert!(cache.get("current").is_some());
+        write_entry(&cache, "old-format", ENTRY_VERSION - 1, detector::fingerprint());
+        assert!(cache.get("old-format").is_none());
+        write_entry(&cache, "old-detector", ENTRY_VERSION, "v0".to_string());
+        assert!(cache.get("old-detector").is_none());
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_entries_are_misses() {
+        let cache = cache("corrupt");
+        fs::create_dir_all(&cache.dir).unwrap();
+        fs::write(cache.entry_path("truncated"), b"{\"version\": 2, \"detec").unwrap();
+        assert!(cache.get("truncated").is_none());
+        assert!(cache.get("missing").is_none());
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+use crate::detector::Conversion;
+use crate::library::AnalyzedGame;
+use peppi::model::game::FIRST_FRAME_INDEX;
+use serde_json::{json, Value};
+use std::fs;
+
+/// Which conversions become clips, and how much of the game around them to include.
+#[derive(Clone, Debug)]
+pub struct ClipOptions {
+    /// Frames to show before the first hit.
+    pub padding_before: usize,
+    /// Frames to keep playing after the conversion ends.
+    pub padding_after: usize,
+    pub min_damage: f32,
+    pub kills_only: bool,
+}
+
+impl Default for ClipOptions {
+    fn default() -> ClipOptions {
+        ClipOptions {
+            padding_before: 120,
+            padding_after: 60,
+            min_damage: 0.0,
+            kills_only: false,
+        }
+    }
+}
+
+impl ClipOptions {
+    pub fn matches(&self, conversion: &Conversion) -> bool {
+        conversion.damage() >= self.min_damage && (conversion.did_kill || !self.kills_only)
+    }
+}
+
+/// A Slippi Dolphin playback queue (see the Slippi comm spec) with a clip for every matching
+/// conversion, in the order they were played. Dolphin plays it with `-i queue.json`.
+pub fn playback_queue(games: &[AnalyzedGame], options: &ClipOptions) -> Value {
+    let mut queue = Vec::new();
+    for game in games {
+        // Dolphin needs absolute paths, since it won't be started from here.
+        let path = fs::canonicalize(&game.path).unwrap_or_else(|_| game.path.clone());
+        let last_frame = game.info.duration as i32 - 1;
+        for conversion in game.conversions.iter().filter(|c| options.matches(c)) {
+            let end = conversion.end_frame.unwrap_or(conversion.start_frame);
+            let start_frame = game_frame(conversion.start_frame.saturating_sub(options.padding_before));
+            let end_frame = game_frame(end + options.padding_after).min(last_frame);
+            queue.push(json!({
+                "path": path.display().to_string(),
+                "startFrame": start_frame,
+                "endFrame": end_frame,
+            }));
+        }
+    }
+
+    json!({
+        "mode": "queue",
+        "replay": "",
+        "isRealTimeMode": false,
+        "outputOverlayFiles": true,
+        "queue": queue,
+    })
+}
+
+/// Frames here count from the first frame of the replay, but Dolphin uses the game's frame numbers,
+/// which start at -123.
+fn game_frame(frame: usize) -> i32 {
+    frame as i32 + FIRST_FRAME_INDEX
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, attack, conversion};
+
+    /// `(startFrame, endFrame)` of each clip.
+    fn clips(games: &[AnalyzedGame], options: &ClipOptions) -> Vec<(i64, i64)> {
+        let queue = playback_queue(games, options);
+        let clips = queue["queue"].as_array().unwrap();
+        clips.iter().map(|c| (c["startFrame"].as_i64().unwrap(), c["endFrame"].as_i64().unwrap())).collect()
+    }
+
+    #[test]
+    fn pads_clips_in_game_frames() {
+        // From frame 100 to 200, counted from the first frame.
+        let game = testing::game(Vec::new(), vec![conversion(0, 1, vec![attack(0, None, 0.0)], 10.0, false)]);
+        let options = ClipOptions { padding_before: 30, padding_after: 20, ..ClipOptions::default() };
+        assert_eq!(clips(&[game], &options), [(100 - 30 - 123, 200 + 20 - 123)]);
+    }
+
+    #[test]
+    fn clips_stay_inside_the_game() {
+        // The padding before would start before the first frame.
+        let mut early = conversion(0, 1, vec![attack(0, None, 0.0)], 10.0, false);
+        early.start_frame = 10;
+        let mut late = conversion(0, 1, vec![attack(0, None, 0.0)], 10.0, false);
+        late.start_frame = 1000;
+        late.end_frame = Some(3700);
+        // 3600 frames after Go, so the last one is 3599.
+        let game = testing::game(Vec::new(), vec![early, late]);
+        assert_eq!(clips(&[game], &ClipOptions::default()), [(-123, 200 + 60 - 123), (1000 - 120 - 123, 3599)]);
+    }
+
+    #[test]
+    fn filters_by_damage_and_kills() {
+        let conversions = vec![
+            conversion(0, 1, vec![attack(0, None, 0.0)], 10.0, false),
+            conversion(0, 1, vec![attack(0, None, 0.0)], 40.0, false),
+            conversion(0, 1, vec![attack(0, None, 80.0)], 100.0, true),
+        ];
+        let games = [testing::game(Vec::new(), conversions)];
+        let count = |options: ClipOptions| clips(&games, &options).len();
+        assert_eq!(count(ClipOptions::default()), 3);
+        assert_eq!(count(ClipOptions { min_damage: 20.0, ..ClipOptions::default() }), 2);
+        assert_eq!(count(ClipOptions { kills_only: true, ..ClipOptions::default() }), 1);
+        assert_eq!(count(ClipOptions { min_damage: 30.0, kills_only: true, ..ClipOptions::default() }), 0);
+    }
+}
@@ -0,0 +1,310 @@
+use crate::detector;
+use crate::frameinfo::{get_character_string, get_stage_string};
+use crate::library::{self, AnalyzedGame};
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use std::fs;
+use std::path::Path;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS games (
+    id INTEGER PRIMARY KEY,
+    hash TEXT NOT NULL UNIQUE,
+    path TEXT NOT NULL,
+    stage TEXT NOT NULL,
+    date TEXT,
+    duration INTEGER NOT NULL,
+    end_method TEXT NOT NULL,
+    slippi_version TEXT NOT NULL,
+    detector TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS players (
+    game_id INTEGER NOT NULL REFERENCES games(id),
+    player_index INTEGER NOT NULL,
+    port TEXT NOT NULL,
+    character TEXT NOT NULL,
+    costume INTEGER NOT NULL,
+    tag TEXT,
+    netplay_name TEXT,
+    connect_code TEXT,
+    final_stocks INTEGER NOT NULL,
+    final_percent REAL NOT NULL,
+    won INTEGER NOT NULL,
+    PRIMARY KEY (game_id, player_index)
+);
+CREATE TABLE IF NOT EXISTS stocks (
+    game_id INTEGER NOT NULL REFERENCES games(id),
+    player_index INTEGER NOT NULL,
+    frame INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS conversions (
+    id INTEGER PRIMARY KEY,
+    game_id INTEGER NOT NULL REFERENCES games(id),
+    attacker_index INTEGER,
+    defender_index INTEGER NOT NULL,
+    start_frame INTEGER NOT NULL,
+    end_frame INTEGER,
+    start_percent REAL NOT NULL,
+    end_percent REAL,
+    did_kill INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS hits (
+    conversion_id INTEGER NOT NULL REFERENCES conversions(id),
+    hit_index INTEGER NOT NULL,
+    attacker_index INTEGER,
+    attack TEXT NOT NULL,
+    frame INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS players_game ON players(game_id);
+CREATE INDEX IF NOT EXISTS players_code ON players(connect_code);
+CREATE INDEX IF NOT EXISTS stocks_game ON stocks(game_id);
+CREATE INDEX IF NOT EXISTS conversions_game ON conversions(game_id);
+CREATE INDEX IF NOT EXISTS hits_conversion ON hits(conversion_id);
+";
+
+/// Canned queries for the `db` command. Anything else is run as SQL.
+const QUERIES: [(&str, &str); 5] = [
+    (
+        "players",
+        "SELECT COALESCE(connect_code, netplay_name, tag) AS player, COUNT(*) AS games, SUM(won) AS wins
+         FROM players GROUP BY player HAVING player IS NOT NULL ORDER BY games DESC",
+    ),
+    (
+        "characters",
+        "SELECT character, COUNT(*) AS games, SUM(won) AS wins, ROUND(100.0 * SUM(won) / COUNT(*), 1) AS win_rate
+         FROM players GROUP BY character ORDER BY games DESC",
+    ),
+    (
+        "stages",
+        "SELECT stage, COUNT(*) AS games, ROUND(AVG(duration) / 60.0, 1) AS average_seconds
+         FROM games GROUP BY stage ORDER BY games DESC",
+    ),
+    (
+        "openers",
+        "SELECT p.character, h.attack, COUNT(*) AS openings, ROUND(AVG(c.end_percent - c.start_percent), 1) AS average_damage
+         FROM conversions c
+         JOIN hits h ON h.conversion_id = c.id AND h.hit_index = 0
+         JOIN players p ON p.game_id = c.game_id AND p.player_index = c.attacker_index
+         GROUP BY p.character, h.attack ORDER BY openings DESC LIMIT 25",
+    ),
+    (
+        "kill-moves",
+        "SELECT p.character, h.attack, COUNT(*) AS kills, ROUND(AVG(c.end_percent), 1) AS average_percent
+         FROM conversions c
+         JOIN hits h ON h.conversion_id = c.id
+             AND h.hit_index = (SELECT MAX(hit_index) FROM hits WHERE conversion_id = c.id)
+         JOIN players p ON p.game_id = c.game_id AND p.player_index = c.attacker_index
+         WHERE c.did_kill GROUP BY p.character, h.attack ORDER BY kills DESC LIMIT 25",
+    ),
+];
+
+/// Analyzed replays stored in SQLite, so they only need to be parsed once.
+pub struct Database {
+    conn: Connection,
+}
+
+impl Database {
+    pub fn open(path: &Path) -> Result<Database> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        // Databases from before the detector was recorded get an empty one, so everything in them is redone.
+        let has_detector: bool = conn.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('games') WHERE name = 'detector'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_detector {
+            conn.execute("ALTER TABLE games ADD COLUMN detector TEXT NOT NULL DEFAULT ''", [])?;
+        }
+        Ok(Database { conn })
+    }
+
+    /// The `detector::fingerprint()` the replay with this hash was analyzed with, if it's stored.
+    pub fn detector(&self, hash: &str) -> Result<Option<String>> {
+        self.conn
+            .query_row("SELECT detector FROM games WHERE hash = ?1", [hash], |row| row.get(0))
+            .optional()
+    }
+
+    /// Stores one analyzed game and everything in it, replacing anything already stored for it.
+    pub fn insert(&mut self, hash: &str, game: &AnalyzedGame) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "DELETE FROM hits WHERE conversion_id IN
+                 (SELECT c.id FROM conversions c JOIN games g ON g.id = c.game_id WHERE g.hash = ?1)",
+            [hash],
+        )?;
+        for table in ["conversions", "stocks", "players"] {
+            tx.execute(
+                &format!("DELETE FROM {} WHERE game_id IN (SELECT id FROM games WHERE hash = ?1)", table),
+                [hash],
+            )?;
+        }
+        tx.execute("DELETE FROM games WHERE hash = ?1", [hash])?;
+
+        let info = &game.info;
+        tx.execute(
+            "INSERT INTO games (hash, path, stage, date, duration, end_method, slippi_version, detector)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                hash,
+                game.path.display().to_string(),
+                get_stage_string(info.stage),
+                info.date.map(|d| d.to_rfc3339()),
+                info.duration as i64,
+                info.end_method_string(),
+                info.slippi_version.to_string(),
+                detector::fingerprint(),
+            ],
+        )?;
+        let game_id = tx.last_insert_rowid();
+
+        for (i, player) in info.players.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO players (game_id, player_index, port, character, costume, tag, netplay_name,
+                     connect_code, final_stocks, final_percent, won)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    game_id,
+                    i as i64,
+                    player.port.to_string(),
+                    get_character_string(player.character),
+                    player.costume,
+                    player.tag,
+                    player.netplay_name,
+                    player.connect_code,
+                    player.final_stocks,
+                    player.final_percent,
+                    info.winners.contains(&player.port),
+                ],
+            )?;
+        }
+
+        for stock in &game.stocks_lost {
+            tx.execute(
+                "INSERT INTO stocks (game_id, player_index, frame) VALUES (?1, ?2, ?3)",
+                params![game_id, stock.player_index as i64, stock.frame as i64],
+            )?;
+        }
+
+        for conversion in &game.conversions {
+            tx.execute(
+                "INSERT INTO conversions (game_id, attacker_index, defender_index, start_frame, end_frame,
+                     start_percent, end_percent, did_kill)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    game_id,
+                    conversion.adv_index.map(|i| i as i64),
+                    conversion.disadv_index as i64,
+                    conversion.start_frame as i64,
+                    conversion.end_frame.map(|f| f as i64),
+                    conversion.start_percent,
+                    conversion.end_percent,
+                    conversion.did_kill,
+                ],
+            )?;
+            let conversion_id = tx.last_insert_rowid();
+            for (i, hit) in conversion.attacks.iter().enumerate() {
+                tx.execute(
+                    "INSERT INTO hits (conversion_id, hit_index, attacker_index, attack, frame)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![conversion_id, i as i64, hit.player_index.map(|i| i as i64), hit.name(), hit.frame as i64],
+                )?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Adds every replay under `dir` that isn't already stored, and redoes any that were analyzed
+    /// by a different version of the detector. Returns how many were added and how many were redone.
+    pub fn import_dir(&mut self, dir: &Path) -> Result<(usize, usize)> {
+        let (mut added, mut updated) = (0, 0);
+        let fingerprint = detector::fingerprint();
+        for path in library::find_replays(dir) {
+            let bytes = match fs::read(&path) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    eprintln!("Skipping {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            let hash = library::hash_bytes(&bytes);
+            let stored = self.detector(&hash)?;
+            if stored.as_ref() == Some(&fingerprint) {
+                continue;
+            }
+            match library::analyze_replay_from(&path, &mut bytes.as_slice()) {
+                Ok(game) => {
+                    self.insert(&hash, &game)?;
+                    match stored {
+                        Some(_) => updated += 1,
+                        None => added += 1,
+                    }
+                }
+                Err(e) => eprintln!("Skipping {}: {}", path.display(), e),
+            }
+        }
+        Ok((added, updated))
+    }
+
+    /// Runs one of the canned queries by name, or `query` itself as SQL, and returns the
+    /// column names followed by each row.
+    pub fn query(&self, query: &str) -> Result<Vec<Vec<String>>> {
+        let sql = QUERIES.iter().find(|(name, _)| *name == query).map_or(query, |(_, sql)| sql);
+        let mut stmt = self.conn.prepare(sql)?;
+        let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+        let width = columns.len();
+
+        let mut table = vec![columns];
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let mut values = Vec::with_capacity(width);
+            for i in 0..width {
+                values.push(match row.get_ref(i)? {
+                    ValueRef::Null => "-".to_string(),
+                    ValueRef::Integer(n) => n.to_string(),
+                    ValueRef::Real(n) => n.to_string(),
+                    ValueRef::Text(s) => String::from_utf8_lossy(s).to_string(),
+                    ValueRef::Blob(b) => format!("<{} bytes>", b.len()),
+                });
+            }
+            table.push(values);
+        }
+        Ok(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::{env, process};
+
+    #[test]
+    fn imports_each_replay_once_per_detector() {
+        let dir = env::temp_dir().join(format!("slipnsights-database-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.slp"), testing::fair_replay(300)).unwrap();
+        fs::write(dir.join("b.slp"), testing::fair_replay(400)).unwrap();
+
+        let mut db = Database::open(&dir.join("games.db")).unwrap();
+        assert_eq!(db.import_dir(&dir).unwrap(), (2, 0));
+        assert_eq!(db.query("SELECT attack FROM hits").unwrap(), [["attack"], ["FAIR"], ["FAIR"]]);
+        // Nothing changed, so nothing is redone.
+        assert_eq!(db.import_dir(&dir).unwrap(), (0, 0));
+
+        // As if one of them was analyzed by an older detector.
+        db.conn.execute("UPDATE games SET detector = 'v0' WHERE path LIKE '%a.slp'", []).unwrap();
+        assert_eq!(db.import_dir(&dir).unwrap(), (0, 1));
+        assert_eq!(db.query("SELECT COUNT(*) FROM conversions").unwrap(), [["COUNT(*)"], ["2"]]);
+        assert_eq!(db.import_dir(&dir).unwrap(), (0, 0));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bad_queries_are_errors() {
+        let db = Database::open(Path::new(":memory:")).unwrap();
+        assert!(db.query("SELECT nothing FROM nowhere").is_err());
+        assert_eq!(db.query("players").unwrap(), [["player", "games", "wins"]]);
+    }
+}
@@ -0,0 +1,1056 @@
+use crate::di::{self, DirectionalInfluence, HitlagTracker};
+use crate::events::ConversionSink;
+use crate::frameinfo::{get_attack_string, get_item_string, PlayerFrame};
+use crate::library;
+use crate::stages::{self, StageZone};
+use core::fmt::{self, Display};
+use peppi::model::enums::attack::Attack;
+use peppi::model::enums::item::Type as ItemType;
+use peppi::model::enums::stage::Stage;
+use peppi::model::frame::{Frame, PortData};
+use peppi::model::game::FIRST_FRAME_INDEX;
+use peppi::model::item::Item;
+use peppi::model::primitives::{Port, Position};
+use serde::{Deserialize, Serialize};
+
+/// Bump this whenever a change to the detector's logic changes what it finds, so cached analysis is
+/// redone. Changes to the settings below, DI or stage geometry are picked up by `fingerprint` on their own.
+pub const DETECTOR_VERSION: u32 = 14;
+
+/// A conversion ends once the defender has been actionable this long without being hit. Replays from
+/// before Slippi 2.0 don't record hitstun, so there it's this long since the last hit instead (once
+/// they've been actionable on the ground).
+pub const CONVERSION_TIMEOUT_FRAMES: usize = 45;
+
+/// Items further than this from the defender when they got hit can't have been what hit them.
+/// Player positions are at their feet and item positions at their center, so this is a rough
+/// allowance of about a tall character's height plus a small hitbox, not a number from the game.
+pub const ITEM_HIT_DISTANCE: f32 = 25.0;
+
+/// The state items start out in: held for items that are pulled or drawn (e.g. Peach's turnips), or
+/// lying on the stage for items that appear there. Projectiles start out in it too, but they're moving.
+const ITEM_SPAWN_STATE: u8 = 0;
+
+/// Chances to follow up shorter than this are too tight to count as dropped.
+pub const MIN_FOLLOW_UP_FRAMES: usize = 5;
+
+/// Opening types, named the same as in slippi-js.
+pub const NEUTRAL_WIN: &str = "neutral-win";
+pub const COUNTER_ATTACK: &str = "counter-attack";
+pub const TRADE: &str = "trade";
+
+/// Identifies the detector's version and everything it can be tuned with. Analysis done with a
+/// different fingerprint is out of date.
+pub fn fingerprint() -> String {
+    format!(
+        "v{} timeout={} item-distance={} item-spawn-state={} follow-up={} {} stages={}",
+        DETECTOR_VERSION,
+        CONVERSION_TIMEOUT_FRAMES,
+        ITEM_HIT_DISTANCE,
+        ITEM_SPAWN_STATE,
+        MIN_FOLLOW_UP_FRAMES,
+        di::settings(),
+        library::hash_bytes(stages::settings().as_bytes())
+    )
+}
+
+/// Finds conversions in a game one frame at a time, reporting them to a `ConversionSink` as they happen.
+///
+/// Only the previous frame's data is kept around, so frames can be pushed as they are parsed
+/// (or streamed) instead of collecting the whole game first.
+pub struct ConversionDetector<const N: usize, S: ConversionSink> {
+    ports: [Port; N],
+    stage: Stage,
+    sink: S,
+    prev_ports: Option<[PortData; N]>,
+    /// Items from the previous frame, since projectiles are often gone by the frame they hit on.
+    prev_items: Vec<Item>,
+    last_frame: usize,
+    active_conversions: [Option<Conversion>; N],
+    /// DI being tracked for each player's last hit, while they're in hitlag.
+    hitlag: [Option<HitlagTracker>; N],
+}
+
+impl<const N: usize, S: ConversionSink> ConversionDetector<N, S> {
+    /// `ports` are the ports in use, lowest first, in the same order as `Frame::ports`.
+    pub fn new(ports: [Port; N], stage: Stage, sink: S) -> Self {
+        ConversionDetector {
+            ports,
+            stage,
+            sink,
+            prev_ports: None,
+            prev_items: Vec::new(),
+            last_frame: 0,
+            active_conversions: [(); N].map(|_| None),
+            hitlag: [None; N],
+        }
+    }
+
+    /// Processes the next frame of the game. Frames must be pushed in order, without repeats.
+    pub fn push_frame(&mut self, frame: &Frame<N>) {
+        let i = (frame.index - FIRST_FRAME_INDEX) as usize;
+        let prev_ports = self.prev_ports.as_ref().unwrap_or(&frame.ports);
+        let mut started = Vec::new();
+
+        for (port, active) in self.active_conversions.iter_mut().enumerate() {
+            let player_frame = &frame.ports[port];
+            let did_lose_stock = player_frame.did_lose_stock(&prev_ports[port]);
+
+            match active {
+                Some(active_conversion) => {
+                    active_conversion.frames_since_last_hit += 1;
+                    active_conversion.has_been_grounded_actionable = active_conversion
+                        .has_been_grounded_actionable
+                        || player_frame.is_grounded_actionable();
+                    let is_actionable = player_frame.is_actionable();
+                    active_conversion.actionable_frames = match is_actionable {
+                        Some(true) => active_conversion.actionable_frames + 1,
+                        _ => 0,
+                    };
+
+                    if let Some(tracker) = self.hitlag[port].as_mut() {
+                        let stick = joystick(player_frame);
+                        match player_frame.is_in_hitlag() {
+                            Some(true) => tracker.push(stick),
+                            Some(false) => {
+                                let knockback = player_frame.leader.post.velocities.map(|v| (v.knockback.x, v.knockback.y));
+                                let di = tracker.finish(stick, knockback);
+                                if let Some(attack) = active_conversion.attacks.last_mut() {
+                                    attack.di = Some(di);
+                                }
+                                self.hitlag[port] = None;
+                            }
+                            None => self.hitlag[port] = None,
+                        }
+                    }
+
+                    let attacker_free = active_conversion.adv_index.is_some_and(|adv_i| frame.ports[adv_i].is_free());
+                    if attacker_free && player_frame.is_in_hitstun_or_tumble() {
+                        let window = active_conversion.follow_up_window.get_or_insert(FrameWindow { start: i, end: i });
+                        window.end = i;
+                        let window = *window;
+                        if active_conversion.missed_follow_up.is_none_or(|missed| window.frames() > missed.frames()) {
+                            active_conversion.missed_follow_up = Some(window);
+                        }
+                    } else {
+                        active_conversion.follow_up_window = None;
+                    }
+
+                    let timed_out = match is_actionable {
+                        Some(_) => active_conversion.actionable_frames > CONVERSION_TIMEOUT_FRAMES,
+                        None => {
+                            active_conversion.frames_since_last_hit > CONVERSION_TIMEOUT_FRAMES
+                                && active_conversion.has_been_grounded_actionable
+                        }
+                    };
+
+                    let mut conversion_complete = false;
+
+                    if timed_out || did_lose_stock {
+                        conversion_complete = true;
+                    } else {
+                        let is_damaged = player_frame.is_damaged();
+                        let is_grabbed = player_frame.is_grabbed();
+                        let is_command_grabbed = player_frame.is_command_grabbed();
+                        let damage_taken = player_frame.damage_taken(&prev_ports[port]);
+
+                        if (is_damaged || is_grabbed || is_command_grabbed) && damage_taken > 0.0 {
+                            active_conversion.frames_since_last_hit = 0;
+                            active_conversion.has_been_grounded_actionable = false;
+
+                            let last_hit_by = player_frame.leader.post.last_hit_by;
+                            let adv_index = player_index(&self.ports, last_hit_by);
+
+                            let item = adv_index
+                                .filter(|adv_i| !landed_move(frame, prev_ports, *adv_i))
+                                .and_then(|adv_i| item_hit(frame, &self.prev_items, &self.ports, port, adv_i));
+                            // `last_attack_landed` isn't updated for item hits, so it'd be whatever hit before.
+                            let landed_attack: Option<Attack> = match adv_index {
+                                Some(adv_i) if item.is_none() => frame.ports[adv_i].leader.post.last_attack_landed,
+                                _ => None,
+                            };
+                            let adv_attack: PlayerAttack = PlayerAttack {
+                                player_index: adv_index,
+                                attack: landed_attack,
+                                frame: i,
+                                grab: false,
+                                item,
+                                defender_percent: prev_ports[port].percent(),
+                                hitstun: player_frame.hitstun_remaining(),
+                                attacker_location: adv_index.map(|adv_i| Location::new(&frame.ports[adv_i], self.stage)),
+                                di: None,
+                            };
+
+                            self.hitlag[port] = hitlag_tracker(player_frame, &prev_ports[port], adv_attack.attacker_location);
+                            active_conversion.add_attack(adv_attack);
+
+                            if let Some(adv_i) = adv_index {
+                                if active_conversion.adv_index.is_none() {
+                                    active_conversion.adv_index = Some(adv_i);
+                                }
+                            }
+
+                            if let Some(attack) = active_conversion.attacks.last() {
+                                self.sink.on_hit(active_conversion, attack);
+                            }
+                        }
+                    }
+
+                    if conversion_complete {
+                        active_conversion.end_frame = Some(i);
+                        active_conversion.end_percent = Some(frame.ports[port].percent());
+                        active_conversion.did_kill = did_lose_stock;
+                        active_conversion.finish_missed_follow_up();
+                        // On a stock loss this is the first frame of their death animation: percent hasn't
+                        // been reset yet, but they're already frozen past the blast zone, so the location
+                        // comes from the last frame they were alive.
+                        let end = if did_lose_stock { &prev_ports[port] } else { player_frame };
+                        active_conversion.end_location = Some(Location::new(end, self.stage));
+
+                        self.sink.on_conversion_end(active_conversion);
+                        *active = None;
+                        self.hitlag[port] = None;
+                    }
+                }
+                None => {
+                    let is_damaged = player_frame.is_damaged();
+                    let is_grabbed = player_frame.is_grabbed();
+                    let is_command_grabbed = player_frame.is_command_grabbed();
+
+                    if is_damaged || is_grabbed || is_command_grabbed {
+                        let last_hit_by = player_frame.leader.post.last_hit_by;
+                        let adv_index = player_index(&self.ports, last_hit_by);
+
+                        let disadv_index = port;
+                        let start_frame = i;
+                        let start_percent = prev_ports[port].percent();
+
+                        let start_location = Location::new(player_frame, self.stage);
+                        let mut conversion =
+                            Conversion::new(adv_index, disadv_index, start_frame, start_percent, start_location);
+
+                        // Grabs aren't attacks, so `last_attack_landed` would still be whatever hit before.
+                        let grab = is_grabbed && !is_damaged;
+                        let item = match adv_index {
+                            Some(adv_i) if !grab && !landed_move(frame, prev_ports, adv_i) => {
+                                item_hit(frame, &self.prev_items, &self.ports, port, adv_i)
+                            }
+                            _ => None,
+                        };
+                        let landed_attack: Option<Attack> = match adv_index {
+                            Some(adv_i) if !grab && item.is_none() => frame.ports[adv_i].leader.post.last_attack_landed,
+                            _ => None,
+                        };
+                        let adv_attack: PlayerAttack = PlayerAttack {
+                            player_index: adv_index,
+                            attack: landed_attack,
+                            frame: i,
+                            grab,
+                            item,
+                            defender_percent: start_percent,
+                            hitstun: player_frame.hitstun_remaining(),
+                            attacker_location: adv_index.map(|adv_i| Location::new(&frame.ports[adv_i], self.stage)),
+                            di: None,
+                        };
+
+                        self.hitlag[port] = hitlag_tracker(player_frame, &prev_ports[port], adv_attack.attacker_location);
+                        conversion.add_attack(adv_attack);
+                        *active = Some(conversion);
+                        started.push(port);
+                    }
+                }
+            }
+
+            if did_lose_stock {
+                self.sink.on_stock_lost(port, i);
+            }
+        }
+
+        // Openings can only be told apart once every player's conversions for this frame are known.
+        let opening_types: Vec<(usize, &str)> = started
+            .iter()
+            .filter_map(|port| self.active_conversions[*port].as_ref().map(|c| (*port, c)))
+            .map(|(port, conversion)| (port, opening_type(&self.active_conversions, conversion)))
+            .collect();
+        for (port, opening_type) in opening_types {
+            if let Some(conversion) = self.active_conversions[port].as_mut() {
+                conversion.opening_type = Some(opening_type.to_string());
+                self.sink.on_conversion_start(conversion);
+                if let Some(attack) = conversion.attacks.last() {
+                    self.sink.on_hit(conversion, attack);
+                }
+            }
+        }
+
+        self.prev_ports = Some(frame.ports.clone());
+        self.prev_items = frame.items.clone().unwrap_or_default();
+        self.last_frame = i;
+    }
+
+    /// Ends any conversions still in progress at the last pushed frame, and returns the sink.
+    pub fn finish(mut self) -> S {
+        if let Some(prev_ports) = &self.prev_ports {
+            for (port, active) in self.active_conversions.iter_mut().enumerate() {
+                if let Some(mut conversion) = active.take() {
+                    conversion.end_frame = Some(self.last_frame);
+                    conversion.end_percent = Some(prev_ports[port].percent());
+                    conversion.end_location = Some(Location::new(&prev_ports[port], self.stage));
+                    conversion.finish_missed_follow_up();
+                    self.sink.on_conversion_end(&conversion);
+                }
+            }
+        }
+
+        self.sink.on_game_end();
+        self.sink
+    }
+}
+
+/// A trade if the attacker got hit by the defender on the same frame, a counter attack if the
+/// attacker was already being comboed, otherwise a neutral win.
+fn opening_type(active: &[Option<Conversion>], conversion: &Conversion) -> &'static str {
+    let other = conversion.adv_index.and_then(|adv| active[adv].as_ref());
+    match other {
+        Some(other)
+            if other.start_frame == conversion.start_frame
+                && other.adv_index == Some(conversion.disadv_index) =>
+        {
+            TRADE
+        }
+        Some(_) => COUNTER_ATTACK,
+        None => NEUTRAL_WIN,
+    }
+}
+
+/// Starts following the defender's DI for a hit, if they're in hitlag (grabs have none) and we
+/// know where the attacker was.
+fn hitlag_tracker(player_frame: &PortData, prev_frame: &PortData, attacker: Option<Location>) -> Option<HitlagTracker> {
+    if player_frame.is_in_hitlag() != Some(true) {
+        return None;
+    }
+    let defender_x = player_frame.leader.post.position.x;
+    Some(HitlagTracker::new(defender_x, attacker?.x, joystick(prev_frame)))
+}
+
+fn joystick(player_frame: &PortData) -> (f32, f32) {
+    let joystick = player_frame.leader.pre.joystick;
+    (joystick.x, joystick.y)
+}
+
+/// Whether one of the attacker's own moves connected this frame. `last_attack_landed` isn't
+/// updated for item hits, so when it changes the hit wasn't from an item (though the same move
+/// landing twice in a row doesn't change it).
+fn landed_move<const N: usize>(frame: &Frame<N>, prev_ports: &[PortData; N], adv_i: usize) -> bool {
+    frame.ports[adv_i].leader.post.last_attack_landed != prev_ports[adv_i].leader.post.last_attack_landed
+}
+
+/// The type of the attacker's item (projectile, thrown item, etc.) that most likely hit the
+/// defender, this frame or the one before.
+fn item_hit<const N: usize>(frame: &Frame<N>, prev_items: &[Item], ports: &[Port], port: usize, adv_i: usize) -> Option<ItemType> {
+    let items = frame.items.iter().flatten().chain(prev_items);
+    let defender = frame.ports[port].leader.post.position;
+    let attacker = frame.ports[adv_i].leader.post.position;
+    closest_item(items, defender, attacker, ports[adv_i])
+}
+
+/// The closest of `items` to the defender that could have hit them: one nobody else owns, that's
+/// flying or thrown rather than held or lying still, and that's closer to the defender than the
+/// attacker is.
+fn closest_item<'a>(
+    items: impl Iterator<Item = &'a Item>,
+    defender: Position,
+    attacker: Position,
+    attacker_port: Port,
+) -> Option<ItemType> {
+    let distance = |from: Position, to: Position| (from.x - to.x).hypot(from.y - to.y);
+    let attacker_distance = distance(attacker, defender);
+    items
+        .filter(|item| !is_held_or_idle(item))
+        .filter(|item| match item.owner {
+            Some(Some(owner)) => owner == attacker_port,
+            Some(None) => true,
+            // Replays from before Slippi 3.5 don't say who owns an item, so anything nearer the
+            // defender than the attacker could be the defender's own.
+            None => distance(item.position, defender) >= distance(item.position, attacker),
+        })
+        .map(|item| (item.r#type, distance(item.position, defender)))
+        .filter(|(_, d)| *d <= ITEM_HIT_DISTANCE && *d < attacker_distance)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(item_type, _)| item_type)
+}
+
+fn is_held_or_idle(item: &Item) -> bool {
+    item.state.0 == ITEM_SPAWN_STATE && item.velocity.x == 0.0 && item.velocity.y == 0.0
+}
+
+/// Index into `Frame::ports` for the player on `port`.
+fn player_index(ports: &[Port], port: Option<Port>) -> Option<usize> {
+    ports.iter().position(|p| Some(*p) == port)
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Conversion {
+    pub adv_index: Option<usize>,
+    pub disadv_index: usize,
+
+    pub has_been_grounded_actionable: bool,
+    pub frames_since_last_hit: usize,
+    /// Frames in a row the defender has been able to act (always 0 for replays without hitstun).
+    pub actionable_frames: usize,
+
+    pub start_frame: usize,
+    pub end_frame: Option<usize>,
+
+    pub start_percent: f32,
+    pub end_percent: Option<f32>,
+
+    /// Where the defender was when it started and ended.
+    pub start_location: Location,
+    pub end_location: Option<Location>,
+
+    pub attacks: Vec<PlayerAttack>,
+    pub did_kill: bool,
+    pub opening_type: Option<String>,
+
+    /// Frames in a row since the last hit where the attacker was free to act and the defender
+    /// was still in hitstun or tumble.
+    pub follow_up_window: Option<FrameWindow>,
+    /// The longest of those windows, if the conversion ended without a kill, i.e. a dropped punish.
+    pub missed_follow_up: Option<FrameWindow>,
+}
+
+impl Conversion {
+    fn new(
+        adv_index: Option<usize>,
+        disadv_index: usize,
+        start_frame: usize,
+        start_percent: f32,
+        start_location: Location,
+    ) -> Conversion {
+        Conversion {
+            adv_index,
+            disadv_index,
+            has_been_grounded_actionable: false,
+            frames_since_last_hit: 0,
+            actionable_frames: 0,
+            start_frame,
+            end_frame: None,
+            start_percent,
+            end_percent: None,
+            start_location,
+            end_location: None,
+            attacks: Vec::new(),
+            did_kill: false,
+            opening_type: None,
+            follow_up_window: None,
+            missed_follow_up: None,
+        }
+    }
+
+    fn add_attack(&mut self, attack: PlayerAttack) {
+        self.attacks.push(attack);
+        self.frames_since_last_hit = 0;
+        self.actionable_frames = 0;
+        self.has_been_grounded_actionable = false;
+        self.follow_up_window = None;
+        self.missed_follow_up = None;
+    }
+
+    /// Only keeps a missed follow-up if it was long enough and the conversion didn't kill anyway.
+    fn finish_missed_follow_up(&mut self) {
+        self.follow_up_window = None;
+        if self.did_kill || self.missed_follow_up.is_some_and(|w| w.frames() < MIN_FOLLOW_UP_FRAMES) {
+            self.missed_follow_up = None;
+        }
+    }
+
+    /// Percent dealt over the whole conversion (0 while it is still in progress).
+    pub fn damage(&self) -> f32 {
+        self.end_percent.map_or(0.0, |end| end - self.start_percent)
+    }
+}
+impl Display for Conversion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let adv_player = match self.adv_index {
+            Some(i) => format!("{}", i + 1),
+            None => "Unknown".to_string(),
+        };
+
+        let attacks_vec = self
+            .attacks
+            .iter()
+            .map(|a| format!("{}", a))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        let end_location = match &self.end_location {
+            Some(location) => location.to_string(),
+            None => "Unknown".to_string(),
+        };
+
+        let missed_follow_up = match &self.missed_follow_up {
+            Some(window) => format!("\n   Missed a follow-up on frames {} - {}", window.start, window.end),
+            None => String::new(),
+        };
+
+        write!(f, "Conversion! Player {} hit Player {}!\n   Frames: {} - {}\n   They dealt {:.2} damage in {} hits.\n   Attacks: {}\n   From {} to {}{}", adv_player, self.disadv_index+1, self.start_frame, self.end_frame.unwrap_or(0), self.end_percent.unwrap() - self.start_percent, self.attacks.len(), attacks_vec, self.start_location, end_location, missed_follow_up)
+    }
+}
+
+/// A range of frames, counted like `Conversion::start_frame`. Both ends are included.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct FrameWindow {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl FrameWindow {
+    pub fn frames(&self) -> usize {
+        self.end - self.start + 1
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerAttack {
+    pub player_index: Option<usize>,
+    pub attack: Option<Attack>,
+    pub frame: usize,
+    /// The defender was grabbed (not hit), which only happens at the start of a conversion.
+    pub grab: bool,
+    /// The item or projectile that hit them, in which case `attack` is `None`. Items are only
+    /// recorded in replays from Slippi 3.0 on.
+    pub item: Option<ItemType>,
+    /// The defender's percent just before the hit.
+    pub defender_percent: f32,
+    /// Hitstun the defender was put in, as of the frame they were hit.
+    pub hitstun: Option<f32>,
+    pub attacker_location: Option<Location>,
+    /// The defender's DI and SDI, once they're out of hitlag. Not recorded in replays from before Slippi 2.0.
+    pub di: Option<DirectionalInfluence>,
+}
+
+impl PlayerAttack {
+    /// The move's name, e.g. `FAIR`, `GRAB` for grabs, or the item's name (e.g. `FOX_LASER`) for item hits.
+    pub fn name(&self) -> String {
+        match (self.grab, self.item, self.attack) {
+            (true, ..) => "GRAB".to_string(),
+            (_, Some(item), _) => get_item_string(item),
+            (_, _, Some(attack)) => get_attack_string(attack),
+            _ => "Unknown".to_string(),
+        }
+    }
+}
+
+impl Display for PlayerAttack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let attack_number = if let Some(a) = self.attack { a.0 } else { 0 };
+        write!(f, "{} ({})", attack_number, self.name())
+    }
+}
+
+/// A player's position and velocity on one frame, and the part of the stage that's in.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Location {
+    pub x: f32,
+    pub y: f32,
+    /// Total velocity (their own plus knockback). Only recorded in replays from Slippi 3.5 on.
+    pub velocity: Option<(f32, f32)>,
+    /// `None` on stages without known geometry.
+    pub zone: Option<StageZone>,
+}
+
+impl Location {
+    fn new(player_frame: &PortData, stage: Stage) -> Location {
+        let post = &player_frame.leader.post;
+        let velocity = post.velocities.map(|v| {
+            (v.autogenous.x + v.knockback.x, v.autogenous.y + v.knockback.y)
+        });
+        Location {
+            x: post.position.x,
+            y: post.position.y,
+            velocity,
+            zone: stages::zone(stage, post.position.x, post.position.y),
+        }
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let zone = self.zone.map_or("unknown", |z| z.name());
+        write!(f, "{} ({:.1}, {:.1})", zone, self.x, self.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::LiveAnalysis;
+    use crate::testing::{self, standing};
+    use peppi::model::enums::action_state::{Common, State};
+    use peppi::model::enums::item::State as ItemState;
+    use peppi::model::frame::StateFlags;
+    use peppi::model::primitives::Velocity;
+    use std::io;
+
+    /// Runs the detector over frames of two players, numbered from 0.
+    fn detect(frames: &[[PortData; 2]]) -> Vec<Conversion> {
+        let mut detector = ConversionDetector::new([Port::P1, Port::P2], Stage::BATTLEFIELD, Vec::new());
+        for (i, ports) in frames.iter().enumerate() {
+            detector.push_frame(&testing::frame(i, ports.clone()));
+        }
+        detector.finish()
+    }
+
+    /// `len` frames of the first player (at x = -10) and the second (at x = 10) standing around,
+    /// changed frame by frame by `script`.
+    fn timeline(len: usize, script: impl Fn(usize, &mut [PortData; 2])) -> Vec<[PortData; 2]> {
+        (0..len)
+            .map(|i| {
+                let mut ports = [standing(-10.0), standing(10.0)];
+                script(i, &mut ports);
+                ports
+            })
+            .collect()
+    }
+
+    /// The second player is in hitstun from the first player's `attack`, at `percent`.
+    fn hit(ports: &mut [PortData; 2], attack: Attack, percent: f32) {
+        ports[0].leader.post.last_attack_landed = Some(attack);
+        let post = &mut ports[1].leader.post;
+        post.state = State::Common(Common::DAMAGE_HI_1);
+        post.damage = percent;
+        post.last_hit_by = Some(Port::P1);
+        post.flags = Some(StateFlags::HIT_STUN);
+        post.misc_as = Some(10.0);
+    }
+
+    /// The second player back on their feet after being hit to `percent`.
+    fn recovered(ports: &mut [PortData; 2], attack: Attack, percent: f32) {
+        ports[0].leader.post.last_attack_landed = Some(attack);
+        ports[1].leader.post.damage = percent;
+        ports[1].leader.post.last_hit_by = Some(Port::P1);
+    }
+
+    #[test]
+    fn hits_start_and_continue_a_conversion() {
+        let frames = timeline(200, |i, ports| match i {
+            0..10 => {}
+            10..20 => hit(ports, Attack::UP_THROW, 10.0),
+            20..30 => hit(ports, Attack::UAIR, 23.0),
+            _ => recovered(ports, Attack::UAIR, 23.0),
+        });
+        let conversions = detect(&frames);
+        assert_eq!(conversions.len(), 1);
+        let conversion = &conversions[0];
+        assert_eq!(conversion.adv_index, Some(0));
+        assert_eq!(conversion.disadv_index, 1);
+        assert_eq!(conversion.start_frame, 10);
+        assert_eq!(conversion.start_percent, 0.0);
+        assert_eq!(conversion.end_percent, Some(23.0));
+        assert_eq!(conversion.opening_type.as_deref(), Some(NEUTRAL_WIN));
+        assert!(!conversion.did_kill);
+        let attacks: Vec<(usize, String, f32)> =
+            conversion.attacks.iter().map(|a| (a.frame, a.name(), a.defender_percent)).collect();
+        assert_eq!(attacks, [(10, "UP_THROW".to_string(), 0.0), (20, "UAIR".to_string(), 10.0)]);
+    }
+
+    #[test]
+    fn a_stock_loss_ends_a_conversion_as_a_kill() {
+        let frames = timeline(100, |i, ports| match i {
+            0..10 => {}
+            10..50 => hit(ports, Attack::UP_SMASH, 110.0),
+            _ => {
+                recovered(ports, Attack::UP_SMASH, 110.0);
+                ports[1].leader.post.state = State::Common(Common::REBIRTH_WAIT);
+                ports[1].leader.post.stocks = 3;
+            }
+        });
+        let conversions = detect(&frames);
+        assert_eq!(conversions.len(), 1);
+        assert!(conversions[0].did_kill);
+        assert_eq!(conversions[0].end_frame, Some(50));
+        assert_eq!(conversions[0].end_percent, Some(110.0));
+    }
+
+    /// Every event a sink got, in order.
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl ConversionSink for Recorder {
+        fn on_conversion_start(&mut self, conversion: &Conversion) {
+            self.0.push(format!("start {}", conversion.start_frame));
+        }
+        fn on_hit(&mut self, _conversion: &Conversion, attack: &PlayerAttack) {
+            self.0.push(format!("hit {} {}", attack.frame, attack.name()));
+        }
+        fn on_conversion_end(&mut self, conversion: &Conversion) {
+            self.0.push(format!("end {:?} kill={}", conversion.end_frame, conversion.did_kill));
+        }
+        fn on_stock_lost(&mut self, port: usize, frame: usize) {
+            self.0.push(format!("stock {} {}", port, frame));
+        }
+        fn on_game_end(&mut self) {
+            self.0.push("game end".to_string());
+        }
+    }
+
+    #[test]
+    fn sinks_get_events_in_frame_order() {
+        let frames = timeline(100, |i, ports| match i {
+            0..10 => {}
+            10..20 => hit(ports, Attack::NAIR, 40.0),
+            20..50 => hit(ports, Attack::UP_SMASH, 110.0),
+            _ => {
+                recovered(ports, Attack::UP_SMASH, 110.0);
+                ports[1].leader.post.state = State::Common(Common::REBIRTH_WAIT);
+                ports[1].leader.post.stocks = 3;
+            }
+        });
+        let mut detector = ConversionDetector::new([Port::P1, Port::P2], Stage::BATTLEFIELD, Recorder::default());
+        for (i, ports) in frames.into_iter().enumerate() {
+            detector.push_frame(&testing::frame(i, ports));
+        }
+        let events = detector.finish().0;
+        assert_eq!(
+            events,
+            ["start 10", "hit 10 NAIR", "hit 20 UP_SMASH", "end Some(50) kill=true", "stock 1 50", "game end"]
+        );
+    }
+
+    #[test]
+    fn records_where_it_happened() {
+        let frames = timeline(60, |i, ports| match i {
+            0..10 => {}
+            10..20 => hit(ports, Attack::FAIR, 12.0),
+            _ => {
+                hit(ports, Attack::BAIR, 25.0);
+                ports[0].leader.post.position = Position { x: 60.0, y: 0.0 };
+                ports[1].leader.post.position = Position { x: 90.0, y: -20.0 };
+            }
+        });
+        let conversion = &detect(&frames)[0];
+        let at = |l: &Location| (l.x, l.y, l.zone);
+        assert_eq!(at(&conversion.start_location), (10.0, 0.0, Some(StageZone::Center)));
+        assert_eq!(conversion.end_location.as_ref().map(at), Some((90.0, -20.0, Some(StageZone::Offstage))));
+        let attackers: Vec<_> = conversion.attacks.iter().map(|a| a.attacker_location.as_ref().map(at)).collect();
+        assert_eq!(attackers, [Some((-10.0, 0.0, Some(StageZone::Center))), Some((60.0, 0.0, Some(StageZone::Ledge)))]);
+    }
+
+    #[test]
+    fn finish_ends_conversions_still_going() {
+        let frames = timeline(40, |i, ports| {
+            if i >= 10 {
+                hit(ports, Attack::FAIR, 12.0);
+            }
+        });
+        let conversions = detect(&frames);
+        assert_eq!(conversions.len(), 1);
+        assert_eq!(conversions[0].end_frame, Some(39));
+        assert_eq!(conversions[0].end_percent, Some(12.0));
+        assert!(!conversions[0].did_kill);
+    }
+
+    /// Drops the state flags (and with them hitstun), like replays from before Slippi 2.0.
+    fn without_flags(frames: &mut [[PortData; 2]]) {
+        for post in frames.iter_mut().flatten().map(|p| &mut p.leader.post) {
+            post.flags = None;
+            post.misc_as = None;
+        }
+    }
+
+    fn end_frames(conversions: &[Conversion]) -> Vec<Option<usize>> {
+        conversions.iter().map(|c| c.end_frame).collect()
+    }
+
+    #[test]
+    fn times_out_once_actionable_for_long_enough() {
+        // Actionable from frame 20, so frame 20 + CONVERSION_TIMEOUT_FRAMES is one too many.
+        let frames = timeline(200, |i, ports| match i {
+            0..10 => {}
+            10..20 => hit(ports, Attack::FAIR, 12.0),
+            _ => recovered(ports, Attack::FAIR, 12.0),
+        });
+        assert_eq!(end_frames(&detect(&frames)), [Some(20 + CONVERSION_TIMEOUT_FRAMES)]);
+    }
+
+    #[test]
+    fn another_hit_restarts_the_timeout() {
+        let frames = timeline(200, |i, ports| match i {
+            0..10 => {}
+            10..20 => hit(ports, Attack::FAIR, 12.0),
+            20..50 => recovered(ports, Attack::FAIR, 12.0),
+            50..60 => hit(ports, Attack::DASH_ATTACK, 20.0),
+            _ => recovered(ports, Attack::DASH_ATTACK, 20.0),
+        });
+        let conversions = detect(&frames);
+        assert_eq!(end_frames(&conversions), [Some(60 + CONVERSION_TIMEOUT_FRAMES)]);
+        assert_eq!(conversions[0].attacks.len(), 2);
+    }
+
+    #[test]
+    fn times_out_after_the_last_hit_without_hitstun() {
+        // Once back on the ground, it's counted from the hit instead.
+        let mut frames = timeline(200, |i, ports| match i {
+            0..10 => {}
+            10..20 => hit(ports, Attack::FAIR, 12.0),
+            _ => recovered(ports, Attack::FAIR, 12.0),
+        });
+        without_flags(&mut frames);
+        assert_eq!(end_frames(&detect(&frames)), [Some(10 + CONVERSION_TIMEOUT_FRAMES + 1)]);
+    }
+
+    #[test]
+    fn never_times_out_in_the_air_without_hitstun() {
+        let mut frames = timeline(200, |i, ports| match i {
+            0..10 => {}
+            10..20 => hit(ports, Attack::FAIR, 12.0),
+            _ => {
+                recovered(ports, Attack::FAIR, 12.0);
+                ports[1].leader.post.state = State::Common(Common::FALL);
+            }
+        });
+        without_flags(&mut frames);
+        assert_eq!(end_frames(&detect(&frames)), [Some(199)]);
+    }
+
+    /// Hit at frame 10 and kept in hitstun for `hitstun` frames, while the attacker stands around.
+    fn missed_follow_up(hitstun: usize) -> Option<(usize, usize)> {
+        let frames = timeline(200, |i, ports| match i {
+            0..10 => {}
+            _ if i < 10 + hitstun => hit(ports, Attack::FAIR, 12.0),
+            _ => recovered(ports, Attack::FAIR, 12.0),
+        });
+        detect(&frames)[0].missed_follow_up.map(|w| (w.start, w.end))
+    }
+
+    #[test]
+    fn counts_frames_the_attacker_could_have_followed_up() {
+        // The hit's own frame doesn't count.
+        assert_eq!(missed_follow_up(20), Some((11, 29)));
+    }
+
+    #[test]
+    fn short_windows_arent_missed_follow_ups() {
+        assert_eq!(missed_follow_up(MIN_FOLLOW_UP_FRAMES), None);
+        assert_eq!(missed_follow_up(MIN_FOLLOW_UP_FRAMES + 1), Some((11, 10 + MIN_FOLLOW_UP_FRAMES)));
+    }
+
+    #[test]
+    fn following_up_clears_the_window() {
+        let frames = timeline(200, |i, ports| match i {
+            0..10 => {}
+            10..20 => hit(ports, Attack::FAIR, 12.0),
+            20..30 => {
+                hit(ports, Attack::FAIR, 24.0);
+                ports[0].leader.post.state = State::Common(Common::ATTACK_AIR_F);
+            }
+            _ => recovered(ports, Attack::FAIR, 24.0),
+        });
+        let conversions = detect(&frames);
+        assert_eq!(conversions[0].attacks.len(), 2);
+        assert!(conversions[0].missed_follow_up.is_none());
+    }
+
+    #[test]
+    fn kills_clear_the_window() {
+        let frames = timeline(100, |i, ports| match i {
+            0..10 => {}
+            10..30 => hit(ports, Attack::FAIR, 120.0),
+            _ => {
+                recovered(ports, Attack::FAIR, 120.0);
+                ports[1].leader.post.state = State::Common(Common::REBIRTH_WAIT);
+                ports[1].leader.post.stocks = 3;
+            }
+        });
+        let conversions = detect(&frames);
+        assert!(conversions[0].did_kill);
+        assert!(conversions[0].missed_follow_up.is_none());
+    }
+
+    #[test]
+    fn records_di_after_hitlag() {
+        let frames = timeline(100, |i, ports| match i {
+            0..10 => {}
+            10..13 => {
+                hit(ports, Attack::FAIR, 12.0);
+                ports[1].leader.post.flags = Some(StateFlags(StateFlags::HIT_STUN.0 | StateFlags::HIT_LAG.0));
+            }
+            13..30 => {
+                hit(ports, Attack::FAIR, 12.0);
+                ports[1].leader.pre.joystick = Position { x: 1.0, y: 0.0 };
+            }
+            _ => recovered(ports, Attack::FAIR, 12.0),
+        });
+        let di = detect(&frames)[0].attacks[0].di.unwrap();
+        assert_eq!(di.joystick, (1.0, 0.0));
+        assert_eq!(di.kind, crate::di::DiKind::Out);
+    }
+
+    #[test]
+    fn grabs_have_no_di() {
+        let frames = timeline(100, |i, ports| {
+            if i >= 10 {
+                let post = &mut ports[1].leader.post;
+                post.state = State::Common(Common::CAPTURE_WAIT_HI);
+                post.last_hit_by = Some(Port::P1);
+                ports[1].leader.pre.joystick = Position { x: 1.0, y: 0.0 };
+            }
+        });
+        let conversions = detect(&frames);
+        assert!(conversions[0].attacks[0].grab);
+        assert!(conversions[0].attacks[0].di.is_none());
+    }
+
+    /// The first player dash attacks the second on frame 10 with one of their lasers right next to
+    /// the second player. `dash_attacked_before` is whether their last move to land was already a
+    /// dash attack.
+    fn hit_near_a_laser(dash_attacked_before: bool) -> Conversion {
+        let laser = Item {
+            id: 0,
+            r#type: ItemType::FOX_LASER,
+            state: ItemState(0),
+            direction: None,
+            position: Position { x: 5.0, y: 5.0 },
+            velocity: Velocity { x: 2.0, y: 0.0 },
+            damage: 0,
+            timer: 0.0,
+            misc: None,
+            owner: Some(Some(Port::P1)),
+        };
+        let frames = timeline(100, |i, ports| match i {
+            0..10 => ports[0].leader.post.last_attack_landed = dash_attacked_before.then_some(Attack::DASH_ATTACK),
+            10..20 => hit(ports, Attack::DASH_ATTACK, 10.0),
+            _ => recovered(ports, Attack::DASH_ATTACK, 10.0),
+        });
+        let mut detector = ConversionDetector::new([Port::P1, Port::P2], Stage::BATTLEFIELD, Vec::new());
+        for (i, ports) in frames.into_iter().enumerate() {
+            let mut frame = testing::frame(i, ports);
+            frame.items = Some(vec![laser]);
+            detector.push_frame(&frame);
+        }
+        detector.finish().remove(0)
+    }
+
+    #[test]
+    fn melee_hits_next_to_an_item_keep_the_move() {
+        assert_eq!(hit_near_a_laser(false).attacks[0].name(), "DASH_ATTACK");
+        // Nothing new landed, so it must have been the laser.
+        assert_eq!(hit_near_a_laser(true).attacks[0].name(), "FOX_LASER");
+    }
+
+    #[test]
+    fn tells_openings_apart() {
+        let p1_on_p2 = testing::conversion(0, 1, Vec::new(), 10.0, false);
+        let p2_on_p1 = testing::conversion(1, 0, Vec::new(), 10.0, false);
+        let mut earlier = p2_on_p1.clone();
+        earlier.start_frame = 80;
+        let mut unknown_attacker = p1_on_p2.clone();
+        unknown_attacker.adv_index = None;
+
+        assert_eq!(opening_type(&[None, Some(p1_on_p2.clone())], &p1_on_p2), NEUTRAL_WIN);
+        assert_eq!(opening_type(&[Some(p2_on_p1.clone()), Some(p1_on_p2.clone())], &p1_on_p2), TRADE);
+        assert_eq!(opening_type(&[Some(earlier), Some(p1_on_p2.clone())], &p1_on_p2), COUNTER_ATTACK);
+        assert_eq!(opening_type(&[Some(p2_on_p1), None], &unknown_attacker), NEUTRAL_WIN);
+    }
+
+    #[test]
+    fn streaming_a_replay_finds_the_same_conversions() {
+        let replay = testing::fair_replay(400);
+        let (game, _) = library::parse_replay_from(&mut &replay[..]).unwrap();
+        let mut batch = Vec::new();
+        library::detect_conversions(&game, &mut batch);
+
+        let mut analysis = LiveAnalysis::new(Vec::new());
+        peppi::parse(&mut io::BufReader::new(&replay[..]), &mut analysis, None).unwrap();
+        let streamed = analysis.into_sink();
+
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].attacks[0].name(), "FAIR");
+        assert_eq!(format!("{:?}", batch), format!("{:?}", streamed));
+    }
+
+    const DEFENDER: Position = Position { x: 0.0, y: 0.0 };
+    const ATTACKER: Position = Position { x: -30.0, y: 0.0 };
+
+    fn item(r#type: ItemType, state: u8, x: f32, moving: bool, owner: Option<Option<Port>>) -> Item {
+        Item {
+            id: 0,
+            r#type,
+            state: ItemState(state),
+            direction: None,
+            position: Position { x, y: 0.0 },
+            velocity: if moving { Velocity { x: 2.0, y: 0.0 } } else { Velocity { x: 0.0, y: 0.0 } },
+            damage: 0,
+            timer: 0.0,
+            misc: None,
+            owner,
+        }
+    }
+
+    fn closest(items: &[Item]) -> Option<ItemType> {
+        closest_item(items.iter(), DEFENDER, ATTACKER, Port::P1)
+    }
+
+    #[test]
+    fn picks_the_closest_of_the_attackers_items() {
+        let items = [
+            item(ItemType::FOX_LASER, 0, -10.0, true, Some(Some(Port::P1))),
+            item(ItemType::PEACH_TURNIP, 2, -5.0, true, Some(Some(Port::P1))),
+            item(ItemType::FALCO_LASER, 0, -2.0, true, Some(Some(Port::P2))),
+        ];
+        assert_eq!(closest(&items), Some(ItemType::PEACH_TURNIP));
+        assert_eq!(closest(&items[2..]), None);
+    }
+
+    #[test]
+    fn skips_items_too_far_away() {
+        let far = item(ItemType::FOX_LASER, 0, -26.0, true, Some(Some(Port::P1)));
+        assert_eq!(closest(&[far]), None);
+        let behind_attacker = item(ItemType::FOX_LASER, 0, 20.0, true, Some(Some(Port::P1)));
+        assert_eq!(closest_item([behind_attacker].iter(), DEFENDER, Position { x: -10.0, y: 0.0 }, Port::P1), None);
+    }
+
+    #[test]
+    fn skips_held_and_idle_items() {
+        let held = item(ItemType::PEACH_TURNIP, 0, -20.0, false, Some(Some(Port::P1)));
+        assert_eq!(closest(&[held]), None);
+        let thrown = item(ItemType::PEACH_TURNIP, 2, -20.0, true, Some(Some(Port::P1)));
+        assert_eq!(closest(&[thrown]), Some(ItemType::PEACH_TURNIP));
+        let laser = item(ItemType::FOX_LASER, 0, -20.0, true, Some(Some(Port::P1)));
+        assert_eq!(closest(&[laser]), Some(ItemType::FOX_LASER));
+    }
+
+    #[test]
+    fn items_without_owners_have_to_be_nearer_the_attacker() {
+        // Before Slippi 3.5.
+        let near_attacker = item(ItemType::PEACH_TURNIP, 2, -20.0, true, None);
+        assert_eq!(closest(&[near_attacker]), Some(ItemType::PEACH_TURNIP));
+        let near_defender = item(ItemType::PEACH_TURNIP, 2, -5.0, true, None);
+        assert_eq!(closest(&[near_defender]), None);
+        // From 3.5 on, with nobody owning it.
+        let unowned = item(ItemType::PEACH_TURNIP, 2, -5.0, true, Some(None));
+        assert_eq!(closest(&[unowned]), Some(ItemType::PEACH_TURNIP));
+    }
+
+    fn attack(grab: bool, item: Option<ItemType>, attack: Option<Attack>) -> PlayerAttack {
+        PlayerAttack {
+            player_index: Some(0),
+            attack,
+            frame: 0,
+            grab,
+            item,
+            defender_percent: 0.0,
+            hitstun: None,
+            attacker_location: None,
+            di: None,
+        }
+    }
+
+    #[test]
+    fn names_grabs_items_and_attacks() {
+        assert_eq!(attack(true, None, Some(Attack::UP_THROW)).name(), "GRAB");
+        assert_eq!(attack(false, Some(ItemType::FOX_LASER), None).name(), "FOX_LASER");
+        assert_eq!(attack(false, None, Some(Attack::UAIR)).name(), "UAIR");
+        assert_eq!(attack(false, None, None).name(), "Unknown");
+    }
+}
@@ -0,0 +1,354 @@
+use crate::detector::PlayerAttack;
+use crate::identity::Aliases;
+use crate::library::AnalyzedGame;
+use core::fmt::{self, Display};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Stick positions closer to the center than this don't count as an input.
+const DEADZONE: f32 = 0.2875;
+/// SDI only happens when the stick gets at least this far out.
+const SDI_THRESHOLD: f32 = 0.7;
+/// How much of the stick has to be perpendicular to the knockback for DI to be a choice between
+/// surviving and escaping, rather than just a direction.
+const PERPENDICULAR_DI: f32 = 0.5;
+/// Full perpendicular DI changes the launch angle by this much.
+const MAX_DI_DEGREES: f32 = 18.0;
+/// Knockback speed (units per frame) where hits start being about survival rather than combos.
+const STRONG_KNOCKBACK: f32 = 4.0;
+
+/// The thresholds above, so analysis done with different ones can be told apart.
+pub fn settings() -> String {
+    format!(
+        "deadzone={} sdi={} perpendicular={} max-di={} strong={}",
+        DEADZONE, SDI_THRESHOLD, PERPENDICULAR_DI, MAX_DI_DEGREES, STRONG_KNOCKBACK
+    )
+}
+
+/// What the defender did with their DI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum DiKind {
+    /// Stick left in the middle.
+    None,
+    /// Towards the attacker.
+    In,
+    /// Away from the attacker.
+    Out,
+    Up,
+    Down,
+    /// Perpendicular to strong knockback, sending them closer to the diagonal where the blast zones are furthest.
+    Survival,
+    /// Perpendicular to weak knockback, sending them further away from the attacker.
+    Combo,
+}
+
+impl DiKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DiKind::None => "none",
+            DiKind::In => "in",
+            DiKind::Out => "out",
+            DiKind::Up => "up",
+            DiKind::Down => "down",
+            DiKind::Survival => "survival",
+            DiKind::Combo => "combo",
+        }
+    }
+}
+
+/// DI and SDI on one hit.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct DirectionalInfluence {
+    /// Stick position on the first frame of hitstun, which is what decides the DI.
+    pub joystick: (f32, f32),
+    /// Knockback velocity on that frame. Only recorded in replays from Slippi 3.5 on.
+    pub knockback: Option<(f32, f32)>,
+    pub kind: DiKind,
+    /// Separate stick inputs during hitlag, each of which would have nudged them a little (SDI).
+    pub sdi_inputs: usize,
+}
+
+/// Follows a defender's stick through hitlag. Start one on the frame they're hit, push each
+/// frame while they're in hitlag, then `finish` on the first frame they're out of it.
+#[derive(Clone, Copy, Debug)]
+pub struct HitlagTracker {
+    /// +1 if they're to the right of the attacker, -1 if to the left.
+    away: f32,
+    sdi_region: Option<i32>,
+    sdi_inputs: usize,
+}
+
+impl HitlagTracker {
+    /// `previous_stick` is the stick on the frame before the hit, so holding a direction into the hit doesn't count as SDI.
+    pub fn new(defender_x: f32, attacker_x: f32, previous_stick: (f32, f32)) -> HitlagTracker {
+        HitlagTracker {
+            away: if defender_x >= attacker_x { 1.0 } else { -1.0 },
+            sdi_region: sdi_region(previous_stick),
+            sdi_inputs: 0,
+        }
+    }
+
+    pub fn push(&mut self, stick: (f32, f32)) {
+        let region = sdi_region(stick);
+        if region.is_some() && region != self.sdi_region {
+            self.sdi_inputs += 1;
+        }
+        self.sdi_region = region;
+    }
+
+    pub fn finish(self, stick: (f32, f32), knockback: Option<(f32, f32)>) -> DirectionalInfluence {
+        DirectionalInfluence {
+            joystick: stick,
+            knockback,
+            kind: classify(stick, self.away, knockback),
+            sdi_inputs: self.sdi_inputs,
+        }
+    }
+}
+
+/// Which of 8 directions the stick is pushed in, if it's far enough out for SDI.
+fn sdi_region(stick: (f32, f32)) -> Option<i32> {
+    let (x, y) = stick;
+    match x.hypot(y) >= SDI_THRESHOLD {
+        true => Some((y.atan2(x).to_degrees() / 45.0).round() as i32 & 7),
+        false => None,
+    }
+}
+
+/// `away` is +1 if away from the attacker is to the right, -1 if to the left.
+fn classify(stick: (f32, f32), away: f32, knockback: Option<(f32, f32)>) -> DiKind {
+    let (x, y) = stick;
+    if x.hypot(y) < DEADZONE {
+        return DiKind::None;
+    }
+
+    if let Some((kx, ky)) = knockback.filter(|(kx, ky)| kx.hypot(*ky) > 0.0) {
+        let speed = kx.hypot(ky);
+        // Only the part of the stick perpendicular to the knockback changes the angle.
+        let perpendicular = (kx * y - ky * x) / speed;
+        if perpendicular.abs() >= PERPENDICULAR_DI {
+            let angle = ky.atan2(kx).to_degrees();
+            let new_angle = angle + MAX_DI_DEGREES * perpendicular;
+            if speed >= STRONG_KNOCKBACK {
+                if from_diagonal(new_angle) < from_diagonal(angle) {
+                    return DiKind::Survival;
+                }
+            } else if new_angle.to_radians().cos() * away > angle.to_radians().cos() * away {
+                return DiKind::Combo;
+            }
+        }
+    }
+
+    match x.abs() >= y.abs() {
+        true if x * away > 0.0 => DiKind::Out,
+        true => DiKind::In,
+        false if y > 0.0 => DiKind::Up,
+        false => DiKind::Down,
+    }
+}
+
+/// Degrees between an upwards launch angle and the nearest upper diagonal (45 or 135 degrees).
+fn from_diagonal(angle: f32) -> f32 {
+    (angle - 45.0).abs().min((angle - 135.0).abs())
+}
+
+/// How one player DIs when they get hit, added up over any number of games.
+#[derive(Clone, Debug, Default)]
+pub struct DiTendencies {
+    pub hits: usize,
+    pub kinds: BTreeMap<DiKind, usize>,
+    pub sdi_inputs: usize,
+    /// Hits they got followed up on with little or no DI, or DI towards the attacker, where
+    /// combo DI might have got them out. Only counted when the knockback is known (Slippi 3.5 on).
+    pub escapable: usize,
+    /// Hits that killed them without survival DI. Only counted when the knockback is known, since
+    /// survival DI can't be told apart from other DI without it.
+    pub kills_without_survival_di: usize,
+}
+
+impl DiTendencies {
+    fn add_hit(&mut self, di: &DirectionalInfluence, next: Option<&PlayerAttack>, killed: bool) {
+        self.hits += 1;
+        *self.kinds.entry(di.kind).or_default() += 1;
+        self.sdi_inputs += di.sdi_inputs;
+        if di.knockback.is_none() {
+            return;
+        }
+        if next.is_some() && matches!(di.kind, DiKind::None | DiKind::In | DiKind::Down) {
+            self.escapable += 1;
+        }
+        if killed && di.kind != DiKind::Survival {
+            self.kills_without_survival_di += 1;
+        }
+    }
+
+    pub fn share(&self, kind: DiKind) -> f32 {
+        self.kinds.get(&kind).copied().unwrap_or(0) as f32 / self.hits.max(1) as f32
+    }
+}
+
+/// DI tendencies for everyone in `games`, keyed by identity like `PlayerIndex` (so anonymous players
+/// are left out). Hits in replays without hitstun data have no DI and are left out too.
+pub fn tendencies(games: &[AnalyzedGame], aliases: &Aliases) -> BTreeMap<String, DiTendencies> {
+    let mut players: BTreeMap<String, DiTendencies> = BTreeMap::new();
+    for game in games {
+        for conversion in &game.conversions {
+            let defender = match game.info.players.get(conversion.disadv_index).and_then(|p| aliases.identity(p)) {
+                Some(defender) => defender,
+                None => continue,
+            };
+            for (i, attack) in conversion.attacks.iter().enumerate() {
+                if let Some(di) = &attack.di {
+                    let next = conversion.attacks.get(i + 1);
+                    let killed = conversion.did_kill && next.is_none();
+                    players.entry(defender.clone()).or_default().add_hit(di, next, killed);
+                }
+            }
+        }
+    }
+    players
+}
+
+impl Display for DiTendencies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kinds = self
+            .kinds
+            .keys()
+            .map(|kind| format!("{:.0}% {}", self.share(*kind) * 100.0, kind.name()))
+            .collect::<Vec<String>>()
+            .join(", ");
+        writeln!(f, "   DI on {} hits: {}", self.hits, kinds)?;
+        writeln!(f, "   {:.1} SDI inputs per hit", self.sdi_inputs as f32 / self.hits.max(1) as f32)?;
+        writeln!(f, "   {} follow-ups after no DI, in or down (combo DI might have escaped)", self.escapable)?;
+        write!(f, "   {} deaths without survival DI", self.kills_without_survival_di)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use peppi::model::enums::character::External;
+    use peppi::model::primitives::Port;
+
+    fn di(kind: DiKind, knockback: Option<(f32, f32)>) -> DirectionalInfluence {
+        DirectionalInfluence { joystick: (0.0, 0.0), knockback, kind, sdi_inputs: 1 }
+    }
+
+    fn attack() -> PlayerAttack {
+        PlayerAttack {
+            player_index: Some(0),
+            attack: None,
+            frame: 0,
+            grab: false,
+            item: None,
+            defender_percent: 0.0,
+            hitstun: None,
+            attacker_location: None,
+            di: None,
+        }
+    }
+
+    #[test]
+    fn classifies_di() {
+        let strong_up = Some((0.0, 6.0));
+        // Mostly up and a little away from the attacker, not hard enough to kill.
+        let weak_up = Some((0.52, 2.95));
+        let cases = [
+            ((0.1, 0.1), 1.0, None, DiKind::None),
+            ((1.0, 0.0), 1.0, None, DiKind::Out),
+            ((1.0, 0.0), -1.0, None, DiKind::In),
+            ((-1.0, 0.0), 1.0, None, DiKind::In),
+            ((0.0, 1.0), 1.0, None, DiKind::Up),
+            ((0.3, -1.0), 1.0, None, DiKind::Down),
+            // No knockback counts as not knowing it.
+            ((1.0, 0.0), 1.0, Some((0.0, 0.0)), DiKind::Out),
+            // Perpendicular to strong knockback, towards either diagonal.
+            ((1.0, 0.0), 1.0, strong_up, DiKind::Survival),
+            ((-1.0, 0.0), 1.0, strong_up, DiKind::Survival),
+            // Along strong knockback, which doesn't change the angle.
+            ((0.0, 1.0), 1.0, strong_up, DiKind::Up),
+            // Perpendicular to weak knockback: away from the attacker escapes, towards them doesn't.
+            ((1.0, 0.0), 1.0, weak_up, DiKind::Combo),
+            ((-1.0, 0.0), 1.0, weak_up, DiKind::In),
+            // Strong knockback already on the diagonal, DI'd away from it.
+            ((0.0, 1.0), 1.0, Some((5.0, 5.0)), DiKind::Up),
+        ];
+        for (stick, away, knockback, kind) in cases {
+            assert_eq!(classify(stick, away, knockback), kind, "{:?} away {} with {:?}", stick, away, knockback);
+        }
+    }
+
+    #[test]
+    fn sdi_regions() {
+        assert_eq!(sdi_region((0.5, 0.0)), None);
+        assert_eq!(sdi_region((1.0, 0.0)), Some(0));
+        assert_eq!(sdi_region((0.7, 0.7)), Some(1));
+        assert_eq!(sdi_region((0.0, 1.0)), Some(2));
+        assert_eq!(sdi_region((-1.0, 0.0)), Some(4));
+        assert_eq!(sdi_region((0.0, -1.0)), Some(6));
+    }
+
+    #[test]
+    fn counts_new_sdi_directions() {
+        // Holding right into the hit doesn't count, and neither does letting go.
+        let mut tracker = HitlagTracker::new(10.0, -10.0, (1.0, 0.0));
+        for stick in [(1.0, 0.0), (0.0, 0.0), (0.0, 1.0), (0.0, 1.0), (1.0, 0.0)] {
+            tracker.push(stick);
+        }
+        let di = tracker.finish((1.0, 0.0), None);
+        assert_eq!(di.sdi_inputs, 2);
+        assert_eq!(di.kind, DiKind::Out);
+    }
+
+    #[test]
+    fn tendencies_are_keyed_by_identity() {
+        let hit = |di_kind| PlayerAttack { di: Some(di(di_kind, None)), ..attack() };
+        let players = vec![
+            testing::player(Port::P1, External::FOX, Some("ABCD#123")),
+            testing::player(Port::P2, External::MARTH, Some("MANG#0")),
+        ];
+        let mut anonymous = testing::game(players.clone(), vec![testing::conversion(1, 0, vec![hit(DiKind::In)], 10.0, false)]);
+        anonymous.info.players[0].connect_code = None;
+        let games = [
+            testing::game(players.clone(), vec![testing::conversion(1, 0, vec![hit(DiKind::Out)], 10.0, false)]),
+            testing::game(players, vec![testing::conversion(0, 1, vec![hit(DiKind::Up), attack()], 10.0, false)]),
+            anonymous,
+        ];
+        let mut code_changed = games[0].clone();
+        code_changed.info.players[0].connect_code = Some("ABCD#999".to_string());
+
+        let tendencies = tendencies(&[games.as_slice(), &[code_changed]].concat(), &Aliases::parse("Fox guy: ABCD#123, ABCD#999"));
+        assert_eq!(tendencies.keys().collect::<Vec<_>>(), ["Fox guy", "MANG#0"]);
+        assert_eq!(tendencies["Fox guy"].hits, 2);
+        assert_eq!(tendencies["Fox guy"].share(DiKind::Out), 1.0);
+        assert_eq!(tendencies["MANG#0"].hits, 1);
+        assert_eq!(tendencies["MANG#0"].kinds.get(&DiKind::Up), Some(&1));
+    }
+
+    #[test]
+    fn counts_missed_di_with_knockback() {
+        let mut tendencies = DiTendencies::default();
+        let next = attack();
+        tendencies.add_hit(&di(DiKind::In, Some((2.0, 1.0))), Some(&next), false);
+        tendencies.add_hit(&di(DiKind::Combo, Some((2.0, 1.0))), Some(&next), false);
+        tendencies.add_hit(&di(DiKind::None, Some((5.0, 5.0))), None, true);
+        tendencies.add_hit(&di(DiKind::Survival, Some((5.0, 5.0))), None, true);
+        assert_eq!(tendencies.hits, 4);
+        assert_eq!(tendencies.sdi_inputs, 4);
+        assert_eq!(tendencies.escapable, 1);
+        assert_eq!(tendencies.kills_without_survival_di, 1);
+    }
+
+    #[test]
+    fn skips_missed_di_without_knockback() {
+        let mut tendencies = DiTendencies::default();
+        let next = attack();
+        tendencies.add_hit(&di(DiKind::None, None), Some(&next), false);
+        tendencies.add_hit(&di(DiKind::In, None), None, true);
+        assert_eq!(tendencies.hits, 2);
+        assert_eq!(tendencies.share(DiKind::None), 0.5);
+        assert_eq!(tendencies.escapable, 0);
+        assert_eq!(tendencies.kills_without_survival_di, 0);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::detector::{Conversion, PlayerAttack};
+
+/// Receives conversion events in frame order as a game is analyzed.
+///
+/// Every method has an empty default, so a sink only needs to implement the events it cares about.
+pub trait ConversionSink {
+    /// A player was hit while not already in a conversion.
+    fn on_conversion_start(&mut self, _conversion: &Conversion) {}
+    /// A hit landed during a conversion, including the hit that started it.
+    fn on_hit(&mut self, _conversion: &Conversion, _attack: &PlayerAttack) {}
+    /// A conversion finished, either by the defender escaping or by losing a stock.
+    fn on_conversion_end(&mut self, _conversion: &Conversion) {}
+    /// The player at `port` lost a stock on `frame`, whether or not they were in a conversion.
+    fn on_stock_lost(&mut self, _port: usize, _frame: usize) {}
+    /// All frames of the game have been processed.
+    fn on_game_end(&mut self) {}
+}
+
+/// Lets a sink be lent to a detector without giving it up.
+impl<S: ConversionSink + ?Sized> ConversionSink for &mut S {
+    fn on_conversion_start(&mut self, conversion: &Conversion) {
+        (**self).on_conversion_start(conversion)
+    }
+    fn on_hit(&mut self, conversion: &Conversion, attack: &PlayerAttack) {
+        (**self).on_hit(conversion, attack)
+    }
+    fn on_conversion_end(&mut self, conversion: &Conversion) {
+        (**self).on_conversion_end(conversion)
+    }
+    fn on_stock_lost(&mut self, port: usize, frame: usize) {
+        (**self).on_stock_lost(port, frame)
+    }
+    fn on_game_end(&mut self) {
+        (**self).on_game_end()
+    }
+}
+
+/// Collects every finished conversion.
+impl ConversionSink for Vec<Conversion> {
+    fn on_conversion_end(&mut self, conversion: &Conversion) {
+        self.push(conversion.clone());
+    }
+}
+
+/// Prints conversions to stdout as they finish, followed by a total once the game ends.
+#[derive(Default)]
+pub struct PrintSink {
+    count: usize,
+}
+
+impl ConversionSink for PrintSink {
+    fn on_conversion_end(&mut self, conversion: &Conversion) {
+        // println!("{:#?}", conversion) for more detailed output
+        println!("{}", conversion);
+        self.count += 1;
+    }
+
+    fn on_game_end(&mut self) {
+        print!("\nFound {} Conversions in ", self.count)
+    }
+}
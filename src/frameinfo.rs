@@ -1,18 +1,25 @@
-use peppi::model::{
-    enums::{
-        action_state::{Common, State},
-        attack::Attack,
-    },
-    frame::{Data, Frame, PortData},
-};
-use std::cmp;
-use std::prelude::*;
+use peppi::model::enums::attack::Attack;
+use peppi::model::enums::character::External;
+use peppi::model::enums::item::Type as ItemType;
+use peppi::model::enums::stage::Stage;
 
 pub trait PlayerFrame {
     fn is_damaged(&self) -> bool;
     fn is_grabbed(&self) -> bool;
     fn is_command_grabbed(&self) -> bool;
     fn is_grounded_actionable(&self) -> bool;
+    /// Whether they can act: not in hitstun or a grab. `None` for replays from before Slippi 2.0,
+    /// which don't record hitstun.
+    fn is_actionable(&self) -> Option<bool>;
+    /// Whether they're frozen in hitlag, or `None` for replays from before Slippi 2.0.
+    fn is_in_hitlag(&self) -> Option<bool>;
+    /// Standing, moving, jumping or falling, i.e. ready to do anything (including follow up on a hit).
+    fn is_free(&self) -> bool;
+    /// In hitstun, or tumbling after it. Replays from before Slippi 2.0 don't record hitstun, so
+    /// there it's any damage state.
+    fn is_in_hitstun_or_tumble(&self) -> bool;
+    /// Frames of hitstun left (0 when not in hitstun), or `None` for replays from before Slippi 2.0.
+    fn hitstun_remaining(&self) -> Option<f32>;
 
     fn percent(&self) -> f32;
     fn stocks(&self) -> u8;
@@ -128,3 +135,15 @@ pub fn get_attack_string(attack: Attack) -> String {
         _ => "UNNNAMED(".to_string() + &(attack.0 as u32).to_string() + ")",
     }
 }
+
+pub fn get_character_string(character: External) -> String {
+    String::try_from(character).unwrap_or_else(|_| "UNNNAMED(".to_string() + &character.0.to_string() + ")")
+}
+
+pub fn get_item_string(item: ItemType) -> String {
+    String::try_from(item).unwrap_or_else(|_| "UNNNAMED(".to_string() + &item.0.to_string() + ")")
+}
+
+pub fn get_stage_string(stage: Stage) -> String {
+    String::try_from(stage).unwrap_or_else(|_| "UNNNAMED(".to_string() + &stage.0.to_string() + ")")
+}
@@ -0,0 +1,181 @@
+use crate::frameinfo::get_character_string;
+use crate::info::PlayerInfo;
+use crate::library::AnalyzedGame;
+use crate::stats::ConversionStats;
+use core::fmt::{self, Display};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+use std::{fs, io};
+
+/// Maps the codes, names and tags someone has used to a single name for them.
+///
+/// The alias file has one person per line, their name followed by a colon and everything else
+/// they go by, e.g. `Mango: MANG#0, C9 Mang0, MANG0`. Lines starting with `#` are ignored.
+/// Matching ignores case.
+#[derive(Clone, Debug, Default)]
+pub struct Aliases {
+    names: HashMap<String, String>,
+}
+
+impl Aliases {
+    pub fn load(path: &Path) -> io::Result<Aliases> {
+        Ok(Aliases::parse(&fs::read_to_string(path)?))
+    }
+
+    pub fn parse(config: &str) -> Aliases {
+        let mut names = HashMap::new();
+        for line in config.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, aliases) = line.split_once(':').unwrap_or((line, ""));
+            let name = name.trim();
+            names.insert(name.to_lowercase(), name.to_string());
+            for alias in aliases.split(',').map(str::trim).filter(|a| !a.is_empty()) {
+                names.insert(alias.to_lowercase(), name.to_string());
+            }
+        }
+        Aliases { names }
+    }
+
+    /// Who this player is: the first of their connect code, netplay name or tag that has an alias,
+    /// or otherwise the best name we have for them. `None` for players with nothing but a port.
+    pub fn identity(&self, player: &PlayerInfo) -> Option<String> {
+        let names = player.names();
+        for name in &names {
+            if let Some(identity) = self.names.get(&name.to_lowercase()) {
+                return Some(identity.clone());
+            }
+        }
+        names.first().map(|n| n.to_string())
+    }
+}
+
+/// Everything we know about one person across a set of replays.
+#[derive(Clone, Debug, Default)]
+pub struct PlayerRecord {
+    pub name: String,
+    /// Connect codes, netplay names and tags they showed up as.
+    pub seen_as: BTreeSet<String>,
+    /// Games played as each character.
+    pub characters: BTreeMap<String, usize>,
+    pub stats: ConversionStats,
+}
+
+/// Players across a set of replays, keyed by identity instead of port.
+#[derive(Clone, Debug, Default)]
+pub struct PlayerIndex {
+    pub players: BTreeMap<String, PlayerRecord>,
+}
+
+impl PlayerIndex {
+    pub fn new(games: &[AnalyzedGame], aliases: &Aliases) -> PlayerIndex {
+        let mut index = PlayerIndex::default();
+        for game in games {
+            index.add_game(game, aliases);
+        }
+        index
+    }
+
+    /// Anonymous players (offline, no tag) can't be told apart between games, so they're left out.
+    pub fn add_game(&mut self, game: &AnalyzedGame, aliases: &Aliases) {
+        for (player, stats) in game.info.players.iter().zip(game.player_stats()) {
+            let name = match aliases.identity(player) {
+                Some(name) => name,
+                None => continue,
+            };
+            let record = self.players.entry(name.clone()).or_default();
+            record.name = name;
+            record.seen_as.extend(player.names().into_iter().map(str::to_string));
+            *record.characters.entry(get_character_string(player.character)).or_default() += 1;
+            record.stats.add(&stats);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PlayerRecord> {
+        self.players.get(name)
+    }
+}
+
+impl Display for PlayerRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let characters = self
+            .characters
+            .iter()
+            .map(|(character, games)| format!("{} ({})", character, games))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let seen_as = self.seen_as.iter().cloned().collect::<Vec<String>>().join(", ");
+
+        writeln!(f, "{}", self.name)?;
+        writeln!(f, "   Seen as: {}", seen_as)?;
+        writeln!(f, "   Characters: {}", characters)?;
+        write!(f, "   {}", self.stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use peppi::model::enums::character::External;
+    use peppi::model::primitives::Port;
+
+    fn player(connect_code: Option<&str>, netplay_name: Option<&str>, tag: Option<&str>) -> PlayerInfo {
+        PlayerInfo {
+            port: Port::P1,
+            character: External::FOX,
+            costume: 0,
+            tag: tag.map(str::to_string),
+            netplay_name: netplay_name.map(str::to_string),
+            connect_code: connect_code.map(str::to_string),
+            final_stocks: 0,
+            final_percent: 0.0,
+        }
+    }
+
+    const ALIASES: &str = "
+        # Comments and blank lines are skipped.
+
+        Mango: MANG#0, C9 Mang0 ,MANG0
+        Armada
+        Hungrybox: HBOX#305,
+    ";
+
+    #[test]
+    fn parses_names_and_aliases() {
+        let aliases = Aliases::parse(ALIASES);
+        let name = |alias: &str| aliases.names.get(alias).map(String::as_str);
+        assert_eq!(name("mang#0"), Some("Mango"));
+        assert_eq!(name("c9 mang0"), Some("Mango"));
+        assert_eq!(name("mang0"), Some("Mango"));
+        assert_eq!(name("mango"), Some("Mango"));
+        assert_eq!(name("armada"), Some("Armada"));
+        assert_eq!(name("hbox#305"), Some("Hungrybox"));
+        assert_eq!(aliases.names.len(), 7);
+        assert!(!aliases.names.contains_key(""));
+        assert!(!aliases.names.keys().any(|k| k.starts_with('#')));
+    }
+
+    #[test]
+    fn identity_ignores_case() {
+        let aliases = Aliases::parse(ALIASES);
+        assert_eq!(aliases.identity(&player(Some("mang#0"), None, None)).as_deref(), Some("Mango"));
+        assert_eq!(aliases.identity(&player(None, None, Some("ARMADA"))).as_deref(), Some("Armada"));
+    }
+
+    #[test]
+    fn identity_uses_the_first_name_with_an_alias() {
+        let aliases = Aliases::parse(ALIASES);
+        // The connect code has no alias, but the tag does.
+        assert_eq!(aliases.identity(&player(Some("ABCD#123"), None, Some("MANG0"))).as_deref(), Some("Mango"));
+        // Nothing has an alias, so it's their connect code.
+        assert_eq!(aliases.identity(&player(Some("ABCD#123"), Some("abcd"), None)).as_deref(), Some("ABCD#123"));
+        assert_eq!(aliases.identity(&player(None, Some("abcd"), Some("EFGH"))).as_deref(), Some("abcd"));
+    }
+
+    #[test]
+    fn anonymous_players_have_no_identity() {
+        assert_eq!(Aliases::default().identity(&player(None, None, None)), None);
+        assert_eq!(Aliases::parse(ALIASES).identity(&player(None, None, None)), None);
+    }
+}
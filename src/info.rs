@@ -0,0 +1,256 @@
+use crate::frameinfo::{get_character_string, get_stage_string, PlayerFrame};
+use chrono::{DateTime, Duration, Utc};
+use core::fmt::{self, Display};
+use peppi::model::enums::character::External;
+use peppi::model::enums::stage::Stage;
+use peppi::model::frame::Frame;
+use peppi::model::game::{EndMethod, Frames, Game, FIRST_FRAME_INDEX};
+use peppi::model::primitives::Port;
+use peppi::model::slippi::Version;
+use serde::{Deserialize, Serialize};
+
+/// A summary of who played what, where, and how it ended, pulled from a replay's start, end and metadata.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GameInfo {
+    pub stage: Stage,
+    pub players: Vec<PlayerInfo>,
+    /// Frames from "Go!" to the end of the game.
+    pub duration: usize,
+    pub end_method: EndMethod,
+    /// Who quit out with L+R+A+Start, if anyone did.
+    pub lras_initiator: Option<Port>,
+    pub winners: Vec<Port>,
+    pub slippi_version: Version,
+    pub date: Option<DateTime<Utc>>,
+    pub platform: Option<String>,
+    pub console: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlayerInfo {
+    pub port: Port,
+    pub character: External,
+    pub costume: u8,
+    /// In-game name tag.
+    pub tag: Option<String>,
+    pub netplay_name: Option<String>,
+    /// Slippi connect code, e.g. `ABCD#123`.
+    pub connect_code: Option<String>,
+    pub final_stocks: u8,
+    pub final_percent: f32,
+}
+
+impl GameInfo {
+    pub fn new(game: &Game) -> GameInfo {
+        let final_state = match &game.frames {
+            Frames::P1(f) => final_state(f),
+            Frames::P2(f) => final_state(f),
+            Frames::P3(f) => final_state(f),
+            Frames::P4(f) => final_state(f),
+        };
+        let meta_players = game.metadata.players.as_deref().unwrap_or_default();
+
+        let players: Vec<PlayerInfo> = game
+            .start
+            .players
+            .iter()
+            .enumerate()
+            .map(|(i, player)| {
+                // Metadata has netplay names for older replays, game start only has them since v3.9.
+                let netplay = meta_players
+                    .iter()
+                    .find(|p| p.port == player.port)
+                    .and_then(|p| p.netplay.as_ref())
+                    .map(|n| (n.name.clone(), n.code.clone()))
+                    .or_else(|| player.netplay.as_ref().map(|n| (n.name.clone(), n.code.clone())));
+                let (final_stocks, final_percent) = final_state.get(i).copied().unwrap_or((0, 0.0));
+                PlayerInfo {
+                    port: player.port,
+                    character: player.character,
+                    costume: player.costume,
+                    tag: player.name_tag.clone().filter(|t| !t.is_empty()),
+                    netplay_name: netplay.as_ref().map(|(name, _)| name.clone()),
+                    connect_code: netplay.map(|(_, code)| code),
+                    final_stocks,
+                    final_percent,
+                }
+            })
+            .collect();
+
+        let lras_initiator = game.end.lras_initiator.flatten();
+        GameInfo {
+            stage: game.start.stage,
+            duration: game.frames.len().saturating_sub(-FIRST_FRAME_INDEX as usize),
+            end_method: game.end.method,
+            lras_initiator,
+            winners: winners(&players, game.end.method, lras_initiator),
+            players,
+            slippi_version: game.start.slippi.version,
+            date: game.metadata.date,
+            platform: game.metadata.platform.clone(),
+            console: game.metadata.console.clone(),
+        }
+    }
+
+    pub fn player(&self, port: Port) -> Option<&PlayerInfo> {
+        self.players.iter().find(|p| p.port == port)
+    }
+
+    /// When the game ended, going by when it started and how long it lasted.
+    pub fn end_time(&self) -> Option<DateTime<Utc>> {
+        let frames = self.duration as i64 - FIRST_FRAME_INDEX as i64;
+        self.date.map(|date| date + Duration::milliseconds(frames * 1000 / 60))
+    }
+
+    /// Characters in port order, e.g. `FOX vs MARTH`.
+    pub fn matchup(&self) -> String {
+        self.players
+            .iter()
+            .map(|p| get_character_string(p.character))
+            .collect::<Vec<String>>()
+            .join(" vs ")
+    }
+
+    /// How the game ended, in the terms players use: GAME, TIME, LRAS or NO CONTEST.
+    pub fn end_method_string(&self) -> String {
+        match self.end_method {
+            _ if self.lras_initiator.is_some() => "LRAS".to_string(),
+            EndMethod::TIME => "TIME".to_string(),
+            EndMethod::GAME => "GAME".to_string(),
+            EndMethod::RESOLVED => "RESOLVED".to_string(),
+            EndMethod::NO_CONTEST => "NO CONTEST".to_string(),
+            _ => "UNRESOLVED".to_string(),
+        }
+    }
+}
+
+/// Stocks and percent for each player on the last frame.
+fn final_state<const N: usize>(frames: &[Frame<N>]) -> Vec<(u8, f32)> {
+    match frames.last() {
+        Some(frame) => frame.ports.iter().map(|p| (p.stocks(), p.percent())).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Whoever didn't quit wins an LRAS. Otherwise it's whoever has the most stocks left, and on time
+/// the lowest percent breaks ties. Replays before v2.0 only say the game was "resolved", which could
+/// be either, so they get the tiebreak too. A game without a result (e.g. no contest) has no winners.
+fn winners(players: &[PlayerInfo], end_method: EndMethod, lras_initiator: Option<Port>) -> Vec<Port> {
+    if let Some(quitter) = lras_initiator {
+        return players.iter().map(|p| p.port).filter(|p| *p != quitter).collect();
+    }
+    if ![EndMethod::GAME, EndMethod::TIME, EndMethod::RESOLVED].contains(&end_method) {
+        return Vec::new();
+    }
+
+    let most_stocks = players.iter().map(|p| p.final_stocks).max().unwrap_or(0);
+    let remaining = players.iter().filter(|p| p.final_stocks == most_stocks);
+    if end_method != EndMethod::GAME {
+        let lowest_percent = remaining.clone().map(|p| p.final_percent).fold(f32::INFINITY, f32::min);
+        remaining.filter(|p| p.final_percent == lowest_percent).map(|p| p.port).collect()
+    } else {
+        remaining.map(|p| p.port).collect()
+    }
+}
+
+impl PlayerInfo {
+    /// Connect code, netplay name and tag, whichever this player has.
+    pub fn names(&self) -> Vec<&str> {
+        [&self.connect_code, &self.netplay_name, &self.tag].iter().filter_map(|n| n.as_deref()).collect()
+    }
+
+    /// The best name we have for this player: connect code, then netplay name, then tag, then port.
+    pub fn name(&self) -> String {
+        self.connect_code
+            .clone()
+            .or_else(|| self.netplay_name.clone())
+            .or_else(|| self.tag.clone())
+            .unwrap_or_else(|| self.port.to_string())
+    }
+}
+
+impl Display for PlayerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} (costume {})", self.port, get_character_string(self.character), self.costume)?;
+        if let Some(tag) = &self.tag {
+            write!(f, ", tag {}", tag)?;
+        }
+        match (&self.netplay_name, &self.connect_code) {
+            (Some(name), Some(code)) => write!(f, ", {} ({})", name, code)?,
+            (Some(name), None) => write!(f, ", {}", name)?,
+            (None, Some(code)) => write!(f, ", {}", code)?,
+            (None, None) => (),
+        }
+        write!(f, " - {} stocks, {:.1}%", self.final_stocks, self.final_percent)
+    }
+}
+
+impl Display for GameInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.duration / 60;
+        let winners = match self.winners.is_empty() {
+            true => "None".to_string(),
+            _ => self.winners.iter().map(|p| p.to_string()).collect::<Vec<String>>().join(", "),
+        };
+
+        writeln!(f, "Stage: {}", get_stage_string(self.stage))?;
+        for player in &self.players {
+            writeln!(f, "   {}", player)?;
+        }
+        writeln!(f, "Duration: {}:{:02} ({} frames)", seconds / 60, seconds % 60, self.duration)?;
+        writeln!(f, "Ended by: {}", self.end_method_string())?;
+        writeln!(f, "Winner: {}", winners)?;
+        if let Some(date) = self.date {
+            writeln!(f, "Played: {}", date)?;
+        }
+        if let Some(platform) = &self.platform {
+            match &self.console {
+                Some(console) => writeln!(f, "Played on: {} ({})", platform, console)?,
+                None => writeln!(f, "Played on: {}", platform)?,
+            }
+        }
+        write!(f, "Slippi version: {}", self.slippi_version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::player;
+
+    /// Fox on port 1 and Marth on port 2, with the stocks and percent they ended on.
+    fn players(p1: (u8, f32), p2: (u8, f32)) -> Vec<PlayerInfo> {
+        let mut players = vec![player(Port::P1, External::FOX, None), player(Port::P2, External::MARTH, None)];
+        for (player, (stocks, percent)) in players.iter_mut().zip([p1, p2]) {
+            player.final_stocks = stocks;
+            player.final_percent = percent;
+        }
+        players
+    }
+
+    #[test]
+    fn most_stocks_wins() {
+        assert_eq!(winners(&players((0, 80.0), (2, 120.0)), EndMethod::GAME, None), [Port::P2]);
+        assert_eq!(winners(&players((1, 150.0), (0, 0.0)), EndMethod::RESOLVED, None), [Port::P1]);
+    }
+
+    #[test]
+    fn lowest_percent_wins_on_time() {
+        assert_eq!(winners(&players((2, 80.0), (2, 40.0)), EndMethod::TIME, None), [Port::P2]);
+        // Stocks still come first.
+        assert_eq!(winners(&players((3, 150.0), (2, 0.0)), EndMethod::TIME, None), [Port::P1]);
+        assert_eq!(winners(&players((2, 60.0), (2, 60.0)), EndMethod::TIME, None), [Port::P1, Port::P2]);
+    }
+
+    #[test]
+    fn quitting_loses() {
+        // Even when ahead.
+        assert_eq!(winners(&players((4, 0.0), (1, 100.0)), EndMethod::NO_CONTEST, Some(Port::P1)), [Port::P2]);
+    }
+
+    #[test]
+    fn no_contest_has_no_winners() {
+        assert!(winners(&players((4, 0.0), (1, 100.0)), EndMethod::NO_CONTEST, None).is_empty());
+        assert!(winners(&players((4, 0.0), (1, 100.0)), EndMethod::UNRESOLVED, None).is_empty());
+    }
+}
@@ -0,0 +1,163 @@
+use crate::detector::{Conversion, PlayerAttack};
+use crate::frameinfo::get_character_string;
+use crate::library::AnalyzedGame;
+use std::collections::HashMap;
+use std::path::Path;
+use std::{fs, io};
+
+/// The kill percent table used when none is given.
+pub const DEFAULT_KILL_PERCENTS: &str = include_str!("../kill-percents.txt");
+
+/// Percents where each character's moves start killing, optionally per matchup.
+///
+/// The file has one move per line, `ATTACKER MOVE: PERCENT` or `ATTACKER MOVE vs DEFENDER: PERCENT`,
+/// e.g. `FOX UP_SMASH vs JIGGLYPUFF: 80`. Lines starting with `#` are ignored. Matching ignores case.
+#[derive(Clone, Debug, Default)]
+pub struct KillPercents {
+    /// Keyed by attacker, move and defender (`None` for any defender).
+    percents: HashMap<(String, String, Option<String>), f32>,
+}
+
+impl KillPercents {
+    pub fn load(path: &Path) -> io::Result<KillPercents> {
+        KillPercents::parse(&fs::read_to_string(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn parse(config: &str) -> Result<KillPercents, String> {
+        let mut percents = HashMap::new();
+        for line in config.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad_line = || format!("can't read kill percent line: {}", line);
+            let (key, percent) = line.split_once(':').ok_or_else(bad_line)?;
+            let percent: f32 = percent.trim().trim_end_matches('%').parse().map_err(|_| bad_line())?;
+            let key = key.to_uppercase();
+            let (key, defender) = match key.split_once(" VS ") {
+                Some((key, defender)) => (key, Some(defender.trim().to_string())),
+                None => (key.as_str(), None),
+            };
+            let (attacker, attack) = key.trim().split_once(' ').ok_or_else(bad_line)?;
+            percents.insert((attacker.to_string(), attack.trim().to_string(), defender), percent);
+        }
+        Ok(KillPercents { percents })
+    }
+
+    /// When `attack` starts killing, for this matchup if there's a number for it.
+    pub fn kill_percent(&self, attacker: &str, attack: &str, defender: &str) -> Option<f32> {
+        let key = |defender: Option<String>| (attacker.to_uppercase(), attack.to_uppercase(), defender);
+        self.percents
+            .get(&key(Some(defender.to_uppercase())))
+            .or_else(|| self.percents.get(&key(None)))
+            .copied()
+    }
+}
+
+/// How a conversion's last hit compares with when that move should kill.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KillCheck {
+    /// The last hit landed at kill percent but they lived.
+    MissedKill,
+    /// The last hit killed before kill percent.
+    EarlyKill,
+}
+
+/// A conversion whose last hit didn't go as the kill percent table says it should.
+pub struct FlaggedConversion<'a> {
+    pub game: &'a AnalyzedGame,
+    pub conversion: &'a Conversion,
+    /// The conversion's last hit, the one that was checked.
+    pub last: &'a PlayerAttack,
+    pub check: KillCheck,
+    /// The defender's percent when the last hit landed.
+    pub percent: f32,
+    pub kill_percent: f32,
+}
+
+/// Compares the last hit of every conversion in `games` with `table`. Conversions whose last move
+/// isn't in the table (or whose attacker isn't known) are skipped.
+pub fn check<'a>(games: &'a [AnalyzedGame], table: &KillPercents) -> Vec<FlaggedConversion<'a>> {
+    let mut flagged = Vec::new();
+    for game in games {
+        for conversion in &game.conversions {
+            let attacker = conversion.adv_index.and_then(|i| game.info.players.get(i));
+            let defender = game.info.players.get(conversion.disadv_index);
+            let (attacker, defender, last) = match (attacker, defender, conversion.attacks.last()) {
+                (Some(attacker), Some(defender), Some(last)) => (attacker, defender, last),
+                _ => continue,
+            };
+            let kill_percent = match table.kill_percent(
+                &get_character_string(attacker.character),
+                &last.name(),
+                &get_character_string(defender.character),
+            ) {
+                Some(kill_percent) => kill_percent,
+                None => continue,
+            };
+
+            let percent = last.defender_percent;
+            let check = match conversion.did_kill {
+                false if percent >= kill_percent => KillCheck::MissedKill,
+                true if percent < kill_percent => KillCheck::EarlyKill,
+                _ => continue,
+            };
+            flagged.push(FlaggedConversion { game, conversion, last, check, percent, kill_percent });
+        }
+    }
+    flagged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, attack, player};
+    use peppi::model::enums::attack::Attack;
+    use peppi::model::enums::character::External;
+    use peppi::model::primitives::Port;
+
+    const TABLE: &str = "# Up smash\nFOX UP_SMASH: 100\nfox up_smash vs jigglypuff: 80%\n\nMARTH FAIR: 120";
+
+    #[test]
+    fn parses_moves_and_matchups() {
+        let table = KillPercents::parse(TABLE).unwrap();
+        assert_eq!(table.kill_percent("FOX", "UP_SMASH", "MARTH"), Some(100.0));
+        assert_eq!(table.kill_percent("fox", "up_smash", "jigglypuff"), Some(80.0));
+        assert_eq!(table.kill_percent("MARTH", "FAIR", "FOX"), Some(120.0));
+        assert_eq!(table.kill_percent("FOX", "FAIR", "MARTH"), None);
+        assert_eq!(table.kill_percent("MARTH", "UP_SMASH", "FOX"), None);
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        assert_eq!(KillPercents::parse("FOX UP_SMASH 100").unwrap_err(), "can't read kill percent line: FOX UP_SMASH 100");
+        assert_eq!(KillPercents::parse("FOX UP_SMASH: lots").unwrap_err(), "can't read kill percent line: FOX UP_SMASH: lots");
+        assert_eq!(KillPercents::parse("FOX: 100").unwrap_err(), "can't read kill percent line: FOX: 100");
+    }
+
+    #[test]
+    fn default_table_parses() {
+        assert!(KillPercents::parse(DEFAULT_KILL_PERCENTS).is_ok());
+    }
+
+    /// A conversion by Fox on Jigglypuff that ends with an up smash at `percent`.
+    fn conversion(percent: f32, did_kill: bool) -> Conversion {
+        let attacks = vec![attack(0, Some(Attack::NAIR), percent - 10.0), attack(0, Some(Attack::UP_SMASH), percent)];
+        testing::conversion(0, 1, attacks, percent + 17.0, did_kill)
+    }
+
+    #[test]
+    fn flags_missed_and_early_kills() {
+        let conversions = vec![
+            conversion(90.0, false),
+            conversion(70.0, false),
+            conversion(60.0, true),
+            conversion(85.0, true),
+        ];
+        let players = vec![player(Port::P1, External::FOX, None), player(Port::P2, External::JIGGLYPUFF, None)];
+        let game = testing::game(players, conversions);
+        let flagged = check(std::slice::from_ref(&game), &KillPercents::parse(TABLE).unwrap());
+        assert!(flagged.iter().all(|f| f.last.name() == "UP_SMASH"));
+        let flagged: Vec<(KillCheck, f32, f32)> = flagged.iter().map(|f| (f.check, f.percent, f.kill_percent)).collect();
+        assert_eq!(flagged, [(KillCheck::MissedKill, 90.0, 80.0), (KillCheck::EarlyKill, 60.0, 80.0)]);
+    }
+}
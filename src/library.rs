@@ -0,0 +1,197 @@
+use crate::cache::Cache;
+use crate::detector::{Conversion, ConversionDetector};
+use crate::events::ConversionSink;
+use crate::frameinfo::PlayerFrame;
+use crate::info::GameInfo;
+use crate::rollback::{RollbackCollector, RollbackStats};
+use crate::stats::ConversionStats;
+use peppi::model::enums::stage::Stage;
+use peppi::model::frame::Frame;
+use peppi::model::game::{Frames, Game};
+use peppi::model::primitives::Port;
+use peppi::ParseError;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+/// Everything we get out of a single replay: who played, how it went, and the conversions in it.
+/// This is what the cache saves, so changing it means bumping `cache::ENTRY_VERSION`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AnalyzedGame {
+    pub path: PathBuf,
+    pub info: GameInfo,
+    pub conversions: Vec<Conversion>,
+    pub stocks_lost: Vec<StockLoss>,
+    pub rollbacks: RollbackStats,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct StockLoss {
+    pub player_index: usize,
+    pub frame: usize,
+}
+
+/// Collects everything the detector reports about a game.
+#[derive(Default)]
+struct AnalysisSink {
+    conversions: Vec<Conversion>,
+    stocks_lost: Vec<StockLoss>,
+}
+
+impl ConversionSink for AnalysisSink {
+    fn on_conversion_end(&mut self, conversion: &Conversion) {
+        self.conversions.push(conversion.clone());
+    }
+
+    fn on_stock_lost(&mut self, port: usize, frame: usize) {
+        self.stocks_lost.push(StockLoss {
+            player_index: port,
+            frame,
+        });
+    }
+}
+
+impl AnalyzedGame {
+    /// Conversion stats for each player, in the same order as `info.players`.
+    pub fn player_stats(&self) -> Vec<ConversionStats> {
+        let mut stats = vec![ConversionStats::default(); self.info.players.len()];
+        for (player, stats) in self.info.players.iter().zip(stats.iter_mut()) {
+            stats.games = 1;
+            if self.info.winners.contains(&player.port) {
+                stats.wins = 1;
+            }
+        }
+        for conversion in &self.conversions {
+            if let Some(stats) = conversion.adv_index.and_then(|i| stats.get_mut(i)) {
+                stats.add_conversion(conversion);
+            }
+        }
+        stats
+    }
+}
+
+/// Parses a whole replay, keeping track of rollbacks along the way.
+pub fn parse_replay(path: &Path) -> Result<(Game, RollbackStats), ParseError> {
+    let file = fs::File::open(path).map_err(|error| ParseError { pos: None, error })?;
+    parse_replay_from(&mut io::BufReader::new(file))
+}
+
+pub fn parse_replay_from<R: Read>(buf: &mut R) -> Result<(Game, RollbackStats), ParseError> {
+    let mut collector = RollbackCollector::default();
+    peppi::parse(buf, &mut collector, None)?;
+    let game = collector
+        .collector
+        .into_game()
+        .map_err(|error| ParseError { pos: None, error })?;
+    Ok((game, collector.stats))
+}
+
+/// Runs the conversion detector over every frame of `game`.
+pub fn detect_conversions(game: &Game, sink: &mut impl ConversionSink) {
+    let ports: Vec<Port> = game.start.players.iter().map(|p| p.port).collect();
+    match &game.frames {
+        Frames::P1(f) => handle_frames_enum(f, &ports, game.start.stage, sink),
+        Frames::P2(f) => handle_frames_enum(f, &ports, game.start.stage, sink),
+        Frames::P3(f) => handle_frames_enum(f, &ports, game.start.stage, sink),
+        Frames::P4(f) => handle_frames_enum(f, &ports, game.start.stage, sink),
+    }
+}
+
+/// Walks the frames of a game in order, reporting conversions and stock losses to `sink` as they happen.
+fn handle_frames_enum<const N: usize>(frames: &[Frame<N>], ports: &[Port], stage: Stage, sink: &mut impl ConversionSink) {
+    let ports: [Port; N] = ports.try_into().unwrap();
+    let mut detector = ConversionDetector::new(ports, stage, sink);
+    for frame in frames {
+        detector.push_frame(frame);
+    }
+    detector.finish();
+}
+
+/// Parses a replay and finds its conversions.
+pub fn analyze_replay(path: &Path) -> Result<AnalyzedGame, ParseError> {
+    let file = fs::File::open(path).map_err(|error| ParseError { pos: None, error })?;
+    analyze_replay_from(path, &mut io::BufReader::new(file))
+}
+
+/// Like `analyze_replay`, for a replay that has already been read (`path` is just recorded).
+pub fn analyze_replay_from<R: Read>(path: &Path, buf: &mut R) -> Result<AnalyzedGame, ParseError> {
+    let (game, rollbacks) = parse_replay_from(buf)?;
+    Ok(analyze_game(path, &game, rollbacks))
+}
+
+/// Finds the conversions in an already parsed game.
+pub fn analyze_game(path: &Path, game: &Game, rollbacks: RollbackStats) -> AnalyzedGame {
+    let mut sink = AnalysisSink::default();
+    detect_conversions(game, &mut sink);
+    AnalyzedGame {
+        path: path.to_path_buf(),
+        info: GameInfo::new(game),
+        conversions: sink.conversions,
+        stocks_lost: sink.stocks_lost,
+        rollbacks,
+    }
+}
+
+/// Each player's percent on every frame, in the same order as `start.players`.
+pub fn percent_timeline(game: &Game) -> Vec<Vec<f32>> {
+    match &game.frames {
+        Frames::P1(f) => frames_percent(f),
+        Frames::P2(f) => frames_percent(f),
+        Frames::P3(f) => frames_percent(f),
+        Frames::P4(f) => frames_percent(f),
+    }
+}
+
+fn frames_percent<const N: usize>(frames: &[Frame<N>]) -> Vec<Vec<f32>> {
+    (0..N).map(|port| frames.iter().map(|f| f.ports[port].percent()).collect()).collect()
+}
+
+/// A hash of a replay's contents (64-bit FNV-1a, as hex), so the same game is recognized
+/// even if the file gets renamed or moved.
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// Every `.slp` file under `dir`, including subdirectories (Slippi can sort replays into monthly folders).
+/// If `dir` is a file, it's the only replay.
+pub fn find_replays(dir: &Path) -> Vec<PathBuf> {
+    if dir.is_file() {
+        return vec![dir.to_path_buf()];
+    }
+    let mut replays = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return replays,
+    };
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        if path.is_dir() {
+            replays.extend(find_replays(&path));
+        } else if path.extension().is_some_and(|ext| ext == "slp") {
+            replays.push(path);
+        }
+    }
+    replays.sort();
+    replays
+}
+
+/// Analyzes every replay under `dir`, skipping (and reporting) any that fail to parse.
+/// Replays that haven't changed since they were last analyzed come from the cache.
+pub fn analyze_dir(dir: &Path) -> Vec<AnalyzedGame> {
+    let cache = Cache::open_default();
+    find_replays(dir)
+        .iter()
+        .filter_map(|path| match cache.analyze(path) {
+            Ok(game) => Some(game),
+            Err(e) => {
+                eprintln!("Skipping {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect()
+}
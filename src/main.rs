@@ -1,252 +1,415 @@
-use core::fmt::{self, Display};
-use std::time::Instant;
-use frameinfo::{get_attack_string, PlayerFrame};
+use clips::ClipOptions;
+use events::PrintSink;
+use frameinfo::PlayerFrame;
+use identity::{Aliases, PlayerIndex};
+use info::GameInfo;
+use kills::{KillCheck, KillPercents};
+use library::AnalyzedGame;
+use matchups::MatchupReport;
 use peppi::model::enums::action_state::{Common, State};
-use peppi::model::enums::attack::Attack;
-use peppi::model::frame::{Frame, PortData};
-use std::fmt::Debug;
-use std::{fs, io};
-
+use peppi::model::frame::{PortData, StateFlags};
+use peppi::model::game::FIRST_FRAME_INDEX;
+use positions::Positions;
+use query::Query;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Instant;
+use std::{env, fs, io, process};
+
+pub mod cache;
+pub mod clips;
+#[cfg(feature = "sqlite")]
+pub mod database;
+pub mod detector;
+pub mod di;
+pub mod events;
 pub mod frameinfo;
+pub mod identity;
+pub mod info;
+pub mod kills;
+pub mod library;
+pub mod matchups;
+pub mod patterns;
+pub mod plot;
+pub mod positions;
+pub mod query;
+pub mod report;
+pub mod rollback;
+pub mod server;
+pub mod sets;
+pub mod spectator;
+pub mod stages;
+pub mod stats;
+pub mod stream;
+#[cfg(test)]
+mod testing;
+pub mod watch;
 
 fn main() {
-    let init_time = Instant::now();
-    let mut buf = io::BufReader::new(fs::File::open("replays/game.slp").unwrap());
-    let game = peppi::game(&mut buf, None, None).unwrap();
-    let frames_enum = game.frames;
-
-    // for player in game.metadata.players.unwrap().iter() {
-    //     let port = match player.port {
-    //         peppi::model::primitives::Port::P1 => 1,
-    //         peppi::model::primitives::Port::P2 => 2,
-    //         peppi::model::primitives::Port::P3 => 3,
-    //         peppi::model::primitives::Port::P4 => 4,
-    //     };
-    // }
-
-    match frames_enum {
-        peppi::model::game::Frames::P1(f) => handle_frames_enum(f),
-        peppi::model::game::Frames::P2(f) => handle_frames_enum(f),
-        peppi::model::game::Frames::P3(f) => handle_frames_enum(f),
-        peppi::model::game::Frames::P4(f) => handle_frames_enum(f),
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("clips") => match args.get(1) {
+            Some(path) => print_clips(Path::new(path), &args[2..]),
+            None => usage(),
+        },
+        #[cfg(feature = "sqlite")]
+        Some("db") => match (args.get(1), args.get(2).map(String::as_str), args.get(3)) {
+            (Some(db), Some("import"), Some(dir)) => import_replays(Path::new(db), Path::new(dir)),
+            (Some(db), Some(query), None) => print_query(Path::new(db), query),
+            _ => usage(),
+        },
+        #[cfg(not(feature = "sqlite"))]
+        Some("db") => {
+            eprintln!("The db command needs the sqlite feature: cargo run --release --features sqlite -- db ...");
+            process::exit(1);
+        }
+        Some("di") => match args.get(1) {
+            Some(path) => print_di(Path::new(path), args.get(2).map(Path::new)),
+            None => usage(),
+        },
+        Some("heatmap") => match args.get(1) {
+            Some(path) => print_heatmaps(Path::new(path), &args[2..]),
+            None => usage(),
+        },
+        Some("info") => match args.get(1) {
+            Some(path) => print_info(Path::new(path)),
+            None => usage(),
+        },
+        Some("kills") => match args.get(1) {
+            Some(path) => print_kill_checks(Path::new(path), args.get(2).map(Path::new)),
+            None => usage(),
+        },
+        Some("matchups") => match (args.get(1), args.get(2)) {
+            (Some(player), Some(dir)) => print_matchups(player, Path::new(dir), args.get(3).map(Path::new)),
+            _ => usage(),
+        },
+        Some("patterns") => match args.get(1) {
+            Some(path) => print_patterns(Path::new(path), &args[2..]),
+            None => usage(),
+        },
+        Some("players") => match args.get(1) {
+            Some(dir) => print_players(Path::new(dir), args.get(2).map(Path::new)),
+            None => usage(),
+        },
+        Some("plot") => match args.get(1) {
+            Some(path) => write_per_replay(Path::new(path), "svg", plot::game_svg),
+            None => usage(),
+        },
+        Some("report") => match args.get(1) {
+            Some(path) => write_per_replay(Path::new(path), "html", report::render),
+            None => usage(),
+        },
+        Some("search") => match args.get(1) {
+            Some(path) if args.len() > 2 => search(Path::new(path), &args[2..].join(" ")),
+            _ => usage(),
+        },
+        Some("serve") => match args.get(1) {
+            Some(dir) => {
+                let port = args.get(2).map_or(server::DEFAULT_PORT, |p| p.parse().unwrap_or_else(|_| usage()));
+                server::serve(Path::new(dir), port)
+            }
+            None => usage(),
+        },
+        Some("sets") => match args.get(1) {
+            Some(dir) => print_sets(Path::new(dir)),
+            None => usage(),
+        },
+        Some("watch") => match args.get(1) {
+            Some(path) => watch::watch(Path::new(path)),
+            None => usage(),
+        },
+        Some("spectate") => match args.get(1) {
+            Some(address) => spectator::spectate(address),
+            None => usage(),
+        },
+        Some("mock-console") => match args.get(1) {
+            Some(path) => {
+                let port = args.get(2).map_or(spectator::DEFAULT_PORT, |p| p.parse().unwrap_or_else(|_| usage()));
+                spectator::serve_replay(Path::new(path), port)
+            }
+            None => usage(),
+        },
+        Some(path) => analyze(Path::new(path)),
+        None => analyze(Path::new("replays/game.slp")),
     }
+}
+
+fn usage() -> ! {
+    eprintln!("Usage:");
+    eprintln!("    slipnsights-rs [REPLAY]       Find conversions in a replay (default: replays/game.slp)");
+    eprintln!("    slipnsights-rs info REPLAY    Show who played, on what stage, and how the game ended");
+    eprintln!("    slipnsights-rs di PATH [ALIASES]            How each player DIs and SDIs when they get hit (needs replays from Slippi 2.0 on)");
+    eprintln!("    slipnsights-rs heatmap PATH [--json] [--svg DIR] [--aliases FILE]");
+    eprintln!("                                  Where each player spends their time on each stage, as zone shares, raw grids or SVG heatmaps");
+    eprintln!("    slipnsights-rs patterns PATH [--length N] [--top N] [--matchups]");
+    eprintln!("                                  Most common move sequences in conversions for each character (or matchup)");
+    eprintln!("    slipnsights-rs players DIR [ALIASES]        Conversion stats for everyone in a directory of replays");
+    eprintln!("    slipnsights-rs kills PATH [KILL_PERCENTS]   Last hits that should have killed but didn't, and kills earlier than expected");
+    eprintln!("    slipnsights-rs matchups PLAYER DIR [ALIASES]    How PLAYER does against each character");
+    eprintln!("    slipnsights-rs plot PATH                    Write percent and stock graphs as SVG next to a replay (or each replay in a directory)");
+    eprintln!("    slipnsights-rs report PATH                  Write an HTML report next to a replay (or each replay in a directory)");
+    eprintln!("    slipnsights-rs search PATH QUERY            Find conversions, e.g. 'attacker:FOX moves:UP_THROW>UAIR kill'");
+    eprintln!("    slipnsights-rs serve DIR [PORT]             Serve analysis results as JSON over HTTP (default port 8080)");
+    eprintln!("    slipnsights-rs sets DIR       Group a directory of replays into sets, with scores and stats");
+    eprintln!("    slipnsights-rs clips PATH [--before FRAMES] [--after FRAMES] [--min-damage PERCENT] [--kills]");
+    eprintln!("                                  Print a Dolphin playback queue of the conversions in a replay or directory");
+    eprintln!("    slipnsights-rs db DATABASE import DIR       Store a directory of replays in a SQLite database");
+    eprintln!("    slipnsights-rs db DATABASE QUERY            Run a query: players, characters, stages, openers, kill-moves, or SQL");
+    eprintln!("    slipnsights-rs watch PATH     Follow a replay (or a directory of replays) as it is being written");
+    eprintln!("    slipnsights-rs spectate HOST[:PORT]         Follow games live from a console or Slippi relay");
+    eprintln!("    slipnsights-rs mock-console REPLAY [PORT]   Stream a replay to spectators as if it were a console");
+    process::exit(1);
+}
+
+/// The value given for a flag, or the usage if it's missing or isn't the right type.
+fn flag_value<T: FromStr>(value: Option<&String>) -> T {
+    value.and_then(|v| v.parse().ok()).unwrap_or_else(|| usage())
+}
+
+fn analyze(path: &Path) {
+    let init_time = Instant::now();
+    let (game, rollbacks) = library::parse_replay(path).unwrap();
+
+    let mut sink = PrintSink::default();
+    library::detect_conversions(&game, &mut sink);
     let end_time = init_time.elapsed();
     println!("{:#?}", end_time);
+    if rollbacks.rollbacks > 0 {
+        println!("{}", rollbacks);
+    }
+}
 
+fn print_info(path: &Path) {
+    let mut buf = io::BufReader::new(fs::File::open(path).unwrap());
+    let game = peppi::game(&mut buf, None, None).unwrap();
+    println!("{}", GameInfo::new(&game));
 }
 
-fn handle_frames_enum<const N: usize>(frames: Vec<Frame<N>>) {
-    let mut conversions: Vec<Conversion> = Vec::new();
-    let mut active_conversions: [Option<Conversion>; N] = [(); N].map(|_| None);
-    for (i, frame) in frames.iter().enumerate() {
-        let prev_frame = if i > 0 { &frames[i - 1] } else { frame };
-        for port in 0..N {
-            match &mut active_conversions[port] {
-                Some(active_conversion) => {
-                    let player_frame = &frame.ports[port];
-
-                    active_conversion.frames_since_last_hit += 1;
-                    active_conversion.has_been_grounded_actionable = active_conversion
-                        .has_been_grounded_actionable
-                        || player_frame.is_grounded_actionable();
-
-                    let mut conversion_complete = false;
-
-                    if active_conversion.frames_since_last_hit > 45
-                        && active_conversion.has_been_grounded_actionable
-                    {
-                        conversion_complete = true;
-                    } else if player_frame.did_lose_stock(&prev_frame.ports[port]) {
-                        conversion_complete = true;
-                    } else {
-                        let is_damaged = player_frame.is_damaged();
-                        let is_grabbed = player_frame.is_grabbed();
-                        let is_command_grabbed = player_frame.is_command_grabbed();
-                        let damage_taken = player_frame.damage_taken(&prev_frame.ports[port]);
-
-                        if (is_damaged || is_grabbed || is_command_grabbed) && damage_taken > 0.0 {
-                            active_conversion.frames_since_last_hit = 0;
-                            active_conversion.has_been_grounded_actionable = false;
-
-                            let last_hit_by = player_frame.leader.post.last_hit_by;
-                            let adv_index: Option<usize> = if let Some(adv_port) = last_hit_by {
-                                match adv_port {
-                                    peppi::model::primitives::Port::P1 => Some(0),
-                                    peppi::model::primitives::Port::P2 => Some(1),
-                                    peppi::model::primitives::Port::P3 => Some(2),
-                                    peppi::model::primitives::Port::P4 => Some(3),
-                                }
-                            } else {
-                                None
-                            };
-
-                            let landed_attack: Option<Attack> = if let Some(adv_i) = adv_index {
-                                frame.ports[adv_i].leader.post.last_attack_landed
-                            } else {
-                                None
-                            };
-                            let adv_attack: PlayerAttack = PlayerAttack {
-                                player_index: adv_index,
-                                attack: landed_attack,
-                                frame: i,
-                            };
-
-                            active_conversion.add_attack(adv_attack);
-
-                            if let Some(adv_i) = adv_index {
-                                if let None = active_conversion.adv_index {
-                                    active_conversion.adv_index = Some(adv_i);
-                                }
-                            }
-                        }
-                    }
-
-                    if conversion_complete {
-                        active_conversion.end_frame = Some(i);
-                        active_conversion.end_percent = Some(frame.ports[port].percent());
-
-                        conversions.push(active_conversion.clone());
-                        active_conversions[port] = None;
-                    }
-                }
-                None => {
-                    let player_frame = &frame.ports[port];
-                    let is_damaged = player_frame.is_damaged();
-                    let is_grabbed = player_frame.is_grabbed();
-                    let is_command_grabbed = player_frame.is_command_grabbed();
-
-                    if is_damaged || is_grabbed || is_command_grabbed {
-                        let last_hit_by = player_frame.leader.post.last_hit_by;
-                        let adv_index: Option<usize> = if let Some(adv_port) = last_hit_by {
-                            match adv_port {
-                                peppi::model::primitives::Port::P1 => Some(0),
-                                peppi::model::primitives::Port::P2 => Some(1),
-                                peppi::model::primitives::Port::P3 => Some(2),
-                                peppi::model::primitives::Port::P4 => Some(3),
-                            }
-                        } else {
-                            None
-                        };
-
-                        let disadv_index = port;
-                        let start_frame = i;
-                        let start_percent = prev_frame.ports[port].percent();
-
-                        let mut conversion =
-                            Conversion::new(adv_index, disadv_index, start_frame, start_percent);
-
-                        let landed_attack: Option<Attack> = if let Some(adv_i) = adv_index {
-                            frame.ports[adv_i].leader.post.last_attack_landed
-                        } else {
-                            None
-                        };
-                        let adv_attack: PlayerAttack = PlayerAttack {
-                            player_index: adv_index,
-                            attack: landed_attack,
-                            frame: i,
-                        };
-
-                        conversion.add_attack(adv_attack);
-                        active_conversions[port] = Some(conversion);
-                    }
+fn print_patterns(path: &Path, flags: &[String]) {
+    let (mut lengths, mut top, mut by_matchup) = (2..=4, 10, false);
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        match flag.as_str() {
+            "--length" => {
+                let n = flag_value(flags.next());
+                if n == 0 {
+                    usage();
                 }
+                lengths = n..=n;
             }
+            "--top" => top = flag_value(flags.next()),
+            "--matchups" => by_matchup = true,
+            _ => usage(),
         }
     }
+    print!("{}", patterns::mine(&library::analyze_dir(path), lengths, by_matchup, top));
+}
 
-    for conversion in &conversions {
-        // println!("{:#?}", conversion) for more detailed output
-        println!("{}", conversion);
+fn print_di(path: &Path, aliases: Option<&Path>) {
+    let aliases = aliases.map_or_else(Aliases::default, |path| Aliases::load(path).unwrap());
+    for (player, tendencies) in di::tendencies(&library::analyze_dir(path), &aliases) {
+        println!("{}\n{}\n", player, tendencies);
     }
-    print!("\nFound {} Conversions in ", conversions.len())
 }
 
-#[derive(Clone, Debug)]
-struct Conversion {
-    adv_index: Option<usize>,
-    disadv_index: usize,
-
-    has_been_grounded_actionable: bool,
-    frames_since_last_hit: usize,
-
-    start_frame: usize,
-    end_frame: Option<usize>,
+fn print_heatmaps(path: &Path, flags: &[String]) {
+    let (mut json, mut svg_dir, mut aliases) = (false, None, Aliases::default());
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        match flag.as_str() {
+            "--json" => json = true,
+            "--svg" => svg_dir = Some(flags.next().map(Path::new).unwrap_or_else(|| usage())),
+            "--aliases" => aliases = Aliases::load(flags.next().map(Path::new).unwrap_or_else(|| usage())).unwrap(),
+            _ => usage(),
+        }
+    }
 
-    start_percent: f32,
-    end_percent: Option<f32>,
+    // Positions need every frame, which the cache doesn't keep, so each replay is parsed again.
+    let mut positions = Positions::default();
+    for replay in library::find_replays(path) {
+        match library::parse_replay(&replay) {
+            Ok((game, _)) => positions.add_game(&game, &aliases),
+            Err(e) => eprintln!("Skipping {}: {}", replay.display(), e),
+        }
+    }
 
-    attacks: Vec<PlayerAttack>,
-    did_kill: bool,
-    opening_type: Option<String>,
+    if let Some(dir) = svg_dir {
+        fs::create_dir_all(dir).unwrap();
+        for heatmap in &positions.heatmaps {
+            let name = format!("{} {}", frameinfo::get_stage_string(heatmap.stage), heatmap.player);
+            let file_name: String = name.chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect();
+            let out = dir.join(file_name + ".svg");
+            fs::write(&out, heatmap.svg()).unwrap();
+            println!("Wrote {}", out.display());
+        }
+    } else if json {
+        println!("{}", serde_json::to_string(&positions).unwrap());
+    } else {
+        print!("{}", positions);
+    }
 }
 
-impl Conversion {
-    fn new(
-        adv_index: Option<usize>,
-        disadv_index: usize,
-        start_frame: usize,
-        start_percent: f32,
-    ) -> Conversion {
-        Conversion {
-            adv_index,
-            disadv_index,
-            has_been_grounded_actionable: false,
-            frames_since_last_hit: 0,
-            start_frame,
-            end_frame: None,
-            start_percent,
-            end_percent: None,
-            attacks: Vec::new(),
-            did_kill: false,
-            opening_type: None,
+fn print_kill_checks(path: &Path, table: Option<&Path>) {
+    let table = match table {
+        Some(path) => KillPercents::load(path).unwrap(),
+        None => KillPercents::parse(kills::DEFAULT_KILL_PERCENTS).unwrap(),
+    };
+    let games = library::analyze_dir(path);
+    let flagged = kills::check(&games, &table);
+    for (check, title) in [(KillCheck::MissedKill, "Missed kills"), (KillCheck::EarlyKill, "Early kills")] {
+        println!("{}:", title);
+        for flagged in flagged.iter().filter(|f| f.check == check) {
+            let (game, conversion) = (flagged.game, flagged.conversion);
+            let name = |i: Option<usize>| match i.and_then(|i| game.info.players.get(i)) {
+                Some(p) => format!("{} ({})", p.name(), frameinfo::get_character_string(p.character)),
+                None => "Unknown".to_string(),
+            };
+            let last = flagged.last;
+            println!(
+                "{}  frame {} ({})\n   {} {} on {} at {:.0}%, kills from {:.0}%",
+                game.path.display(),
+                last.frame as i32 + FIRST_FRAME_INDEX,
+                plot::game_time(last.frame),
+                name(conversion.adv_index),
+                last.name(),
+                name(Some(conversion.disadv_index)),
+                flagged.percent,
+                flagged.kill_percent
+            );
         }
+        println!();
     }
+    let missed = flagged.iter().filter(|f| f.check == KillCheck::MissedKill).count();
+    println!("Found {} missed kills and {} early kills", missed, flagged.len() - missed);
+}
 
-    fn add_attack(&mut self, attack: PlayerAttack) {
-        self.attacks.push(attack);
-        self.frames_since_last_hit = 0;
-        self.has_been_grounded_actionable = false;
+fn print_players(dir: &Path, aliases: Option<&Path>) {
+    let aliases = aliases.map_or_else(Aliases::default, |path| Aliases::load(path).unwrap());
+    let index = PlayerIndex::new(&library::analyze_dir(dir), &aliases);
+    for player in index.players.values() {
+        println!("{}\n", player);
     }
+    println!("Found {} players", index.players.len());
 }
-impl Display for Conversion {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let adv_player = match self.adv_index {
-            Some(i) => format!("{}", i + 1),
-            None => "Unknown".to_string(),
-        };
 
-        let attacks_vec = self
-            .attacks
-            .iter()
-            .map(|a| format!("{}", a))
-            .collect::<Vec<String>>()
-            .join(", ");
+fn print_matchups(player: &str, dir: &Path, aliases: Option<&Path>) {
+    let aliases = aliases.map_or_else(Aliases::default, |path| Aliases::load(path).unwrap());
+    println!("{}", MatchupReport::new(player, &library::analyze_dir(dir), &aliases));
+}
+
+#[cfg(feature = "sqlite")]
+fn import_replays(db: &Path, dir: &Path) {
+    let mut db = database::Database::open(db).unwrap();
+    let (added, updated) = db.import_dir(dir).unwrap();
+    println!("Added {} new replays and redid {} analyzed by an older version", added, updated);
+}
+
+#[cfg(feature = "sqlite")]
+fn print_query(db: &Path, query: &str) {
+    let db = database::Database::open(db).unwrap();
+    let table = match db.query(query) {
+        Ok(table) => table,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let mut widths = vec![0; table[0].len()];
+    for row in &table {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.len());
+        }
+    }
+    for row in &table {
+        let line: Vec<String> = row.iter().zip(&widths).map(|(v, w)| format!("{:<w$}", v, w = w)).collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
 
-        write!(f, "Conversion! Player {} hit Player {}!\n   Frames: {} - {}\n   They dealt {:.2} damage in {} hits.\n   Attacks: {}", adv_player, self.disadv_index+1, self.start_frame, self.end_frame.unwrap_or(0), self.end_percent.unwrap() - self.start_percent, self.attacks.len(), attacks_vec)
+/// Renders each replay at `path` (a replay, or a directory of them) to a file next to it with the given extension.
+fn write_per_replay(path: &Path, extension: &str, render: fn(&AnalyzedGame, &[Vec<f32>]) -> String) {
+    for replay in library::find_replays(path) {
+        let (game, rollbacks) = match library::parse_replay(&replay) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                eprintln!("Skipping {}: {}", replay.display(), e);
+                continue;
+            }
+        };
+        let analyzed = library::analyze_game(&replay, &game, rollbacks);
+        let out = replay.with_extension(extension);
+        fs::write(&out, render(&analyzed, &library::percent_timeline(&game))).unwrap();
+        println!("Wrote {}", out.display());
     }
 }
 
-#[derive(Debug, Clone)]
-struct PlayerAttack {
-    player_index: Option<usize>,
-    attack: Option<Attack>,
-    frame: usize,
+fn print_clips(path: &Path, flags: &[String]) {
+    let mut options = ClipOptions::default();
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        match flag.as_str() {
+            "--before" => options.padding_before = flag_value(flags.next()),
+            "--after" => options.padding_after = flag_value(flags.next()),
+            "--min-damage" => options.min_damage = flag_value(flags.next()),
+            "--kills" => options.kills_only = true,
+            _ => usage(),
+        }
+    }
+    let queue = clips::playback_queue(&library::analyze_dir(path), &options);
+    println!("{}", serde_json::to_string_pretty(&queue).unwrap());
 }
 
-impl Display for PlayerAttack {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let attack_number = if let Some(a) = self.attack { a.0 } else { 0 };
+fn search(path: &Path, query: &str) {
+    let query = match Query::parse(query) {
+        Ok(query) => query,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let mut found = 0;
+    for game in library::analyze_dir(path) {
+        for conversion in game.conversions.iter().filter(|c| query.matches(&game, c)) {
+            let name = |i: Option<usize>| match i.and_then(|i| game.info.players.get(i)) {
+                Some(p) => format!("{} ({})", p.name(), frameinfo::get_character_string(p.character)),
+                None => "Unknown".to_string(),
+            };
+            let moves = conversion.attacks.iter().map(|a| a.name()).collect::<Vec<String>>().join(" > ");
+            let end = conversion.end_frame.unwrap_or(conversion.start_frame);
+            let missed_follow_up = match &conversion.missed_follow_up {
+                Some(window) => format!(
+                    "\n   Missed a follow-up on frames {} to {}",
+                    window.start as i32 + FIRST_FRAME_INDEX,
+                    window.end as i32 + FIRST_FRAME_INDEX
+                ),
+                None => String::new(),
+            };
+            println!(
+                "{}  frames {} to {} ({})\n   {} on {}: {:.0}% - {:.0}%{}\n   {}{}",
+                game.path.display(),
+                conversion.start_frame as i32 + FIRST_FRAME_INDEX,
+                end as i32 + FIRST_FRAME_INDEX,
+                plot::game_time(conversion.start_frame),
+                name(conversion.adv_index),
+                name(Some(conversion.disadv_index)),
+                conversion.start_percent,
+                conversion.end_percent.unwrap_or(conversion.start_percent),
+                if conversion.did_kill { ", killed" } else { "" },
+                moves,
+                missed_follow_up
+            );
+            found += 1;
+        }
+    }
+    println!("\nFound {} matching conversions", found);
+}
 
-        let player = match self.player_index {
-            Some(i) => format!("{}", i),
-            None => "Unknown".to_string(),
-        };
-        let attack_string = match self.attack {
-            Some(a) => get_attack_string(a),
-            None => "Unknown".to_string(),
-        };
-        write!(f, "{} ({})", attack_number, attack_string)
+fn print_sets(dir: &Path) {
+    let sets = sets::group_sets(library::analyze_dir(dir));
+    for set in &sets {
+        println!("{}\n", set);
     }
+    println!("Found {} sets", sets.len());
 }
 
 impl PlayerFrame for PortData {
@@ -261,10 +424,10 @@ impl PlayerFrame for PortData {
 
         if let State::Common(c) = state {
             let state_id = c.0;
-            return state_id >= damaged_range_start && state_id <= damaged_range_end
-                || state_id == damaged_fall;
+            state_id >= damaged_range_start && state_id <= damaged_range_end
+                || state_id == damaged_fall
         } else {
-            return false;
+            false
         }
     }
 
@@ -277,9 +440,9 @@ impl PlayerFrame for PortData {
 
         if let State::Common(c) = state {
             let state_id = c.0;
-            return state_id >= grab_range_start && state_id <= grab_range_end;
+            state_id >= grab_range_start && state_id <= grab_range_end
         } else {
-            return false;
+            false
         }
     }
 
@@ -297,11 +460,11 @@ impl PlayerFrame for PortData {
 
         if let State::Common(c) = state {
             let state_id = c.0;
-            return (state_id >= cmd_grab_range_start_1 && state_id <= cmd_grab_range_end_1)
+            (state_id >= cmd_grab_range_start_1 && state_id <= cmd_grab_range_end_1)
                 || (state_id >= cmd_grab_range_start_2 && state_id <= cmd_grab_range_end_2)
-                || state_id == cmd_grab_barrel_wait;
+                || state_id == cmd_grab_barrel_wait
         } else {
-            return false;
+            false
         }
     }
 
@@ -322,12 +485,61 @@ impl PlayerFrame for PortData {
 
         if let State::Common(c) = state {
             let state_id = c.0;
-            return state_id >= ground_control_start && state_id <= ground_control_end
+            state_id >= ground_control_start && state_id <= ground_control_end
                 || state_id >= squat_start && state_id <= squat_end
                 || state_id >= ground_attack_start && state_id <= ground_attack_end
-                || state_id == grab;
+                || state_id == grab
+        } else {
+            false
+        }
+    }
+
+    fn is_actionable(&self) -> Option<bool> {
+        let flags = self.leader.post.flags?;
+        // Not hitlag on its own, since that also happens when their own attacks connect.
+        let stunned = flags.0 & (StateFlags::HIT_STUN.0 | StateFlags::DEAD.0) != 0;
+        Some(!stunned && !self.is_grabbed() && !self.is_command_grabbed())
+    }
+
+    fn is_free(&self) -> bool {
+        // Everything from standing around to falling after a double jump, plus crouching.
+        let free_start = Common::WAIT.0;
+        let free_end = Common::FALL_AERIAL_B.0;
+
+        let squat_start = Common::SQUAT.0;
+        let squat_end = Common::SQUAT_RV.0;
+
+        let state = self.leader.post.state;
+
+        if let State::Common(c) = state {
+            let state_id = c.0;
+            let in_hitstun = self.hitstun_remaining().is_some_and(|h| h > 0.0);
+            !in_hitstun
+                && (state_id >= free_start && state_id <= free_end
+                    || state_id >= squat_start && state_id <= squat_end)
         } else {
-            return false;
+            false
+        }
+    }
+
+    fn is_in_hitstun_or_tumble(&self) -> bool {
+        let post = &self.leader.post;
+        match post.flags {
+            Some(flags) => flags.0 & StateFlags::HIT_STUN.0 != 0 || post.state == State::Common(Common::DAMAGE_FALL),
+            None => self.is_damaged(),
+        }
+    }
+
+    fn is_in_hitlag(&self) -> Option<bool> {
+        Some(self.leader.post.flags?.0 & StateFlags::HIT_LAG.0 != 0)
+    }
+
+    fn hitstun_remaining(&self) -> Option<f32> {
+        let post = &self.leader.post;
+        // misc_as is used for other things outside of hitstun.
+        match post.flags? {
+            flags if flags.0 & StateFlags::HIT_STUN.0 != 0 => post.misc_as,
+            _ => Some(0.0),
         }
     }
 
@@ -0,0 +1,205 @@
+use crate::frameinfo::{get_character_string, get_stage_string};
+use crate::identity::Aliases;
+use crate::library::AnalyzedGame;
+use crate::stats::ConversionStats;
+use core::fmt::{self, Display};
+use std::collections::{BTreeMap, HashMap};
+
+/// How many openers and killing moves to list per matchup.
+const TOP_MOVES: usize = 3;
+
+/// How one player does against each character they've played against.
+pub struct MatchupReport {
+    pub player: String,
+    /// Keyed by the opponent's character.
+    pub matchups: BTreeMap<String, Matchup>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Matchup {
+    pub stats: ConversionStats,
+    /// Games and wins on each stage.
+    pub stages: BTreeMap<String, (usize, usize)>,
+    /// Opponent's percent when they died, summed over every kill.
+    pub total_kill_percent: f32,
+    /// First move of each opening, and how often it was used.
+    pub openers: HashMap<String, usize>,
+    /// Last move of each conversion that took a stock.
+    pub killing_moves: HashMap<String, usize>,
+}
+
+impl MatchupReport {
+    /// `player` is matched against everyone's identity, ignoring case. Only 1v1 games count, since with
+    /// more players there's no single opposing character.
+    pub fn new(player: &str, games: &[AnalyzedGame], aliases: &Aliases) -> MatchupReport {
+        let mut report = MatchupReport {
+            player: player.to_string(),
+            matchups: BTreeMap::new(),
+        };
+        for game in games.iter().filter(|g| g.info.players.len() == 2) {
+            let is_player = |i: usize| {
+                aliases.identity(&game.info.players[i]).is_some_and(|name| name.eq_ignore_ascii_case(player))
+            };
+            let (us, them) = match (is_player(0), is_player(1)) {
+                (true, false) => (0, 1),
+                (false, true) => (1, 0),
+                _ => continue,
+            };
+            let opponent = get_character_string(game.info.players[them].character);
+            report.matchups.entry(opponent).or_default().add_game(game, us);
+        }
+        report
+    }
+}
+
+impl Matchup {
+    fn add_game(&mut self, game: &AnalyzedGame, us: usize) {
+        let stats = game.player_stats()[us];
+        self.stats.add(&stats);
+        let stage = self.stages.entry(get_stage_string(game.info.stage)).or_default();
+        stage.0 += 1;
+        stage.1 += stats.wins;
+
+        for conversion in game.conversions.iter().filter(|c| c.adv_index == Some(us)) {
+            if let Some(opener) = conversion.attacks.first() {
+                *self.openers.entry(opener.name()).or_default() += 1;
+            }
+            if conversion.did_kill {
+                self.total_kill_percent += conversion.end_percent.unwrap_or(0.0);
+                if let Some(killer) = conversion.attacks.last() {
+                    *self.killing_moves.entry(killer.name()).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    /// Average percent the opponent died at.
+    pub fn kill_percent(&self) -> Option<f32> {
+        match self.stats.kills {
+            0 => None,
+            n => Some(self.total_kill_percent / n as f32),
+        }
+    }
+}
+
+/// The most used moves first, e.g. `NAIR (4), DASH_ATTACK (2)`.
+fn most_common(moves: &HashMap<String, usize>) -> String {
+    let mut moves: Vec<(&String, &usize)> = moves.iter().collect();
+    moves.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    match moves.is_empty() {
+        true => "None".to_string(),
+        _ => moves
+            .iter()
+            .take(TOP_MOVES)
+            .map(|(name, count)| format!("{} ({})", name, count))
+            .collect::<Vec<String>>()
+            .join(", "),
+    }
+}
+
+impl Display for Matchup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stages = self
+            .stages
+            .iter()
+            .map(|(stage, (games, wins))| format!("{} {}/{}", stage, wins, games))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let kill_percent = match self.kill_percent() {
+            Some(percent) => format!("{:.1}%", percent),
+            None => "-".to_string(),
+        };
+
+        writeln!(f, "   {:.0}% win rate: {}", self.stats.win_rate() * 100.0, self.stats)?;
+        writeln!(f, "   Stages (wins/games): {}", stages)?;
+        writeln!(f, "   Average kill percent: {}", kill_percent)?;
+        writeln!(f, "   Most common openers: {}", most_common(&self.openers))?;
+        write!(f, "   Most common killing moves: {}", most_common(&self.killing_moves))
+    }
+}
+
+impl Display for MatchupReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Matchups for {}", self.player)?;
+        if self.matchups.is_empty() {
+            write!(f, "\n   No 1v1 games found")?;
+        }
+        for (character, matchup) in &self.matchups {
+            write!(f, "\n\nvs {}\n{}", character, matchup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detector::Conversion;
+    use crate::testing::{self, attack, conversion, player};
+    use peppi::model::enums::attack::Attack;
+    use peppi::model::enums::character::External;
+    use peppi::model::enums::stage::Stage;
+    use peppi::model::primitives::Port;
+
+    fn one_v_one(p1: (External, &str), p2: (External, &str), conversions: Vec<Conversion>) -> AnalyzedGame {
+        let players = vec![player(Port::P1, p1.0, Some(p1.1)), player(Port::P2, p2.0, Some(p2.1))];
+        testing::game(players, conversions)
+    }
+
+    fn games() -> Vec<AnalyzedGame> {
+        let nair_uair = vec![attack(0, Some(Attack::NAIR), 0.0), attack(0, Some(Attack::UAIR), 20.0)];
+        let mut marth = one_v_one(
+            (External::FOX, "ABCD#123"),
+            (External::MARTH, "EFGH#456"),
+            vec![
+                conversion(0, 1, nair_uair, 60.0, true),
+                conversion(1, 0, vec![attack(1, Some(Attack::DOWN_SMASH), 0.0)], 50.0, false),
+                conversion(0, 1, vec![attack(0, Some(Attack::FAIR), 60.0)], 80.0, false),
+            ],
+        );
+        marth.info.winners = vec![Port::P1];
+
+        // On the other port this time, with the code typed differently.
+        let falco = one_v_one(
+            (External::FALCO, "EFGH#456"),
+            (External::FOX, "abcd#123"),
+            vec![conversion(1, 0, vec![attack(1, Some(Attack::NAIR), 0.0)], 40.0, false)],
+        );
+
+        let someone_else = one_v_one(
+            (External::FOX, "IJKL#789"),
+            (External::PEACH, "EFGH#456"),
+            vec![conversion(0, 1, vec![attack(0, Some(Attack::NAIR), 0.0)], 40.0, false)],
+        );
+        vec![marth, falco, someone_else]
+    }
+
+    #[test]
+    fn adds_up_each_matchup() {
+        let report = MatchupReport::new("Abcd#123", &games(), &Aliases::default());
+        assert_eq!(report.matchups.keys().collect::<Vec<_>>(), ["FALCO", "MARTH"]);
+
+        let marth = &report.matchups["MARTH"];
+        assert_eq!((marth.stats.games, marth.stats.wins, marth.stats.openings), (1, 1, 2));
+        assert_eq!(marth.stats.win_rate(), 1.0);
+        assert_eq!(marth.stats.damage_per_opening(), 40.0);
+        assert_eq!(marth.kill_percent(), Some(60.0));
+        assert_eq!(most_common(&marth.openers), "FAIR (1), NAIR (1)");
+        assert_eq!(most_common(&marth.killing_moves), "UAIR (1)");
+        assert_eq!(marth.stages[&get_stage_string(Stage::BATTLEFIELD)], (1, 1));
+
+        let falco = &report.matchups["FALCO"];
+        assert_eq!((falco.stats.games, falco.stats.wins), (1, 0));
+        assert_eq!(falco.stats.win_rate(), 0.0);
+        assert_eq!(falco.stats.damage_per_opening(), 40.0);
+        assert_eq!(falco.kill_percent(), None);
+    }
+
+    #[test]
+    fn goes_by_aliases() {
+        let aliases = Aliases::parse("Fox main: ABCD#123, IJKL#789");
+        let report = MatchupReport::new("fox main", &games(), &aliases);
+        assert_eq!(report.matchups.keys().collect::<Vec<_>>(), ["FALCO", "MARTH", "PEACH"]);
+        assert!(MatchupReport::new("ABCD#12", &games(), &aliases).matchups.is_empty());
+    }
+}
@@ -0,0 +1,167 @@
+use crate::frameinfo::get_character_string;
+use crate::library::AnalyzedGame;
+use core::fmt::{self, Display};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::RangeInclusive;
+
+/// How often a sequence of moves shows up in conversions, and how those conversions went.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SequenceStats {
+    /// Conversions with the sequence in them (counted once, however many times it repeats).
+    pub conversions: usize,
+    pub total_damage: f32,
+    pub kills: usize,
+}
+
+impl SequenceStats {
+    pub fn average_damage(&self) -> f32 {
+        self.total_damage / self.conversions.max(1) as f32
+    }
+
+    pub fn kill_rate(&self) -> f32 {
+        self.kills as f32 / self.conversions.max(1) as f32
+    }
+}
+
+/// Move sequences (n-grams of consecutive hits in a conversion) for each character, or each
+/// matchup, with the most common first.
+pub struct Patterns {
+    pub groups: BTreeMap<String, Vec<(Vec<String>, SequenceStats)>>,
+    /// Only the top this many sequences of each group are shown.
+    pub top: usize,
+}
+
+/// Mines sequences of every length in `lengths` from every conversion in `games`. Sequences with an
+/// unknown move in them are left out, since they'd mostly be noise.
+pub fn mine(games: &[AnalyzedGame], lengths: RangeInclusive<usize>, by_matchup: bool, top: usize) -> Patterns {
+    let mut groups: BTreeMap<String, HashMap<Vec<String>, SequenceStats>> = BTreeMap::new();
+    for game in games {
+        for conversion in &game.conversions {
+            let attacker = match conversion.adv_index.and_then(|i| game.info.players.get(i)) {
+                Some(attacker) => get_character_string(attacker.character),
+                None => continue,
+            };
+            let group = match game.info.players.get(conversion.disadv_index) {
+                Some(defender) if by_matchup => format!("{} vs {}", attacker, get_character_string(defender.character)),
+                _ => attacker,
+            };
+
+            let moves: Vec<String> = conversion.attacks.iter().map(|a| a.name()).collect();
+            let mut sequences = HashSet::new();
+            for n in lengths.clone() {
+                for window in moves.windows(n).filter(|w| !w.iter().any(|m| m == "Unknown")) {
+                    sequences.insert(window.to_vec());
+                }
+            }
+
+            let group = groups.entry(group).or_default();
+            for sequence in sequences {
+                let stats = group.entry(sequence).or_default();
+                stats.conversions += 1;
+                stats.total_damage += conversion.damage();
+                if conversion.did_kill {
+                    stats.kills += 1;
+                }
+            }
+        }
+    }
+
+    let groups = groups
+        .into_iter()
+        .map(|(group, sequences)| {
+            let mut sequences: Vec<(Vec<String>, SequenceStats)> = sequences.into_iter().collect();
+            sequences.sort_by(|a, b| b.1.conversions.cmp(&a.1.conversions).then_with(|| a.0.cmp(&b.0)));
+            (group, sequences)
+        })
+        .collect();
+    Patterns { groups, top }
+}
+
+impl Display for Patterns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (group, sequences) in &self.groups {
+            writeln!(f, "{}", group)?;
+            for (sequence, stats) in sequences.iter().take(self.top) {
+                writeln!(
+                    f,
+                    "   {}: {} conversions, {:.1} average damage, {:.0}% kill rate",
+                    sequence.join(" -> "),
+                    stats.conversions,
+                    stats.average_damage(),
+                    stats.kill_rate() * 100.0
+                )?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, attack, conversion, player};
+    use peppi::model::enums::attack::Attack;
+    use peppi::model::enums::character::External;
+    use peppi::model::primitives::Port;
+
+    fn game(defender: External, conversions: Vec<crate::detector::Conversion>) -> AnalyzedGame {
+        testing::game(vec![player(Port::P1, External::FOX, None), player(Port::P2, defender, None)], conversions)
+    }
+
+    fn sequence(moves: &[&str]) -> Vec<String> {
+        moves.iter().map(|m| m.to_string()).collect()
+    }
+
+    #[test]
+    fn counts_sequences_once_per_conversion() {
+        let shine_fair = || {
+            vec![
+                attack(0, Some(Attack::DOWN_SPECIAL), 0.0),
+                attack(0, Some(Attack::FAIR), 5.0),
+                attack(0, Some(Attack::DOWN_SPECIAL), 15.0),
+                attack(0, Some(Attack::FAIR), 20.0),
+            ]
+        };
+        let conversions = vec![conversion(0, 1, shine_fair(), 30.0, true), conversion(0, 1, shine_fair(), 40.0, false)];
+        let games = [game(External::FALCO, conversions)];
+        let patterns = mine(&games, 2..=2, false, 10);
+
+        let fox = &patterns.groups["FOX"];
+        let (_, stats) = fox.iter().find(|(s, _)| *s == sequence(&["DOWN_SPECIAL", "FAIR"])).unwrap();
+        // Twice in each conversion, but there are only two conversions.
+        assert_eq!(stats.conversions, 2);
+        assert_eq!(stats.average_damage(), 35.0);
+        assert_eq!(stats.kill_rate(), 0.5);
+        assert_eq!(fox[0].0, sequence(&["DOWN_SPECIAL", "FAIR"]));
+    }
+
+    #[test]
+    fn skips_sequences_with_unknown_moves() {
+        let attacks = vec![
+            attack(0, Some(Attack::NAIR), 0.0),
+            attack(0, None, 10.0),
+            attack(0, Some(Attack::UAIR), 20.0),
+        ];
+        let games = [game(External::FALCO, vec![conversion(0, 1, attacks, 30.0, false)])];
+        let patterns = mine(&games, 2..=3, false, 10);
+        assert!(patterns.groups["FOX"].is_empty());
+    }
+
+    #[test]
+    fn groups_by_matchup() {
+        let attacks = || vec![attack(0, Some(Attack::NAIR), 0.0), attack(0, Some(Attack::UAIR), 10.0)];
+        let games = [
+            game(External::FALCO, vec![conversion(0, 1, attacks(), 20.0, false)]),
+            game(External::MARTH, vec![conversion(0, 1, attacks(), 20.0, false)]),
+        ];
+
+        let by_character = mine(&games, 2..=2, false, 10);
+        assert_eq!(by_character.groups.keys().collect::<Vec<_>>(), ["FOX"]);
+        assert_eq!(by_character.groups["FOX"][0].1.conversions, 2);
+
+        let by_matchup = mine(&games, 2..=2, true, 10);
+        assert_eq!(by_matchup.groups.keys().collect::<Vec<_>>(), ["FOX vs FALCO", "FOX vs MARTH"]);
+        assert_eq!(by_matchup.groups["FOX vs MARTH"][0].1.conversions, 1);
+    }
+}
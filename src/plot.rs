@@ -0,0 +1,283 @@
+use crate::frameinfo::get_character_string;
+use crate::library::AnalyzedGame;
+use core::fmt::Write;
+use peppi::model::game::FIRST_FRAME_INDEX;
+use peppi::model::primitives::Port;
+
+/// Line and stock colors for each player, in port order like in game.
+const PLAYER_COLORS: [&str; 4] = ["#e54b4b", "#4b7be5", "#d9b632", "#3fae5a"];
+
+const CHART_WIDTH: usize = 800;
+const CHART_HEIGHT: usize = 200;
+const STOCK_ROW_HEIGHT: usize = 24;
+const LEGEND_HEIGHT: usize = 24;
+
+/// Room for the percent labels on the left and the time labels underneath.
+const MARGIN_LEFT: f32 = 40.0;
+const MARGIN_BOTTOM: f32 = 20.0;
+
+/// Percent is only plotted every this many frames, which is plenty at this size and keeps files small.
+const CHART_STEP: usize = 10;
+/// Seconds between labels on the time axis.
+const TIME_LABEL_SECONDS: usize = 30;
+
+/// Percent and stock graphs for a game in one standalone SVG file, with a legend on top.
+/// `percents` is each player's percent on every frame (see `library::percent_timeline`).
+pub fn game_svg(game: &AnalyzedGame, percents: &[Vec<f32>]) -> String {
+    let frames = percents.first().map_or(0, |p| p.len());
+    let percent_height = CHART_HEIGHT + MARGIN_BOTTOM as usize;
+    let stocks_height = game.info.players.len() * STOCK_ROW_HEIGHT;
+    let mut svg = String::new();
+
+    writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"sans-serif\">",
+        CHART_WIDTH,
+        LEGEND_HEIGHT + percent_height + stocks_height + 8
+    )
+    .unwrap();
+    writeln!(svg, "<rect width=\"100%\" height=\"100%\" fill=\"#fff\"/>").unwrap();
+
+    let mut x = MARGIN_LEFT;
+    for player in &game.info.players {
+        let label = format!("{} ({})", player.name(), get_character_string(player.character));
+        writeln!(
+            svg,
+            "<rect x=\"{}\" y=\"6\" width=\"12\" height=\"12\" fill=\"{}\"/><text x=\"{}\" y=\"16\" font-size=\"12\">{}</text>",
+            x,
+            color(player.port),
+            x + 16.0,
+            escape(&label)
+        )
+        .unwrap();
+        x += 16.0 + label.len() as f32 * 7.0 + 20.0;
+    }
+
+    writeln!(svg, "<svg y=\"{}\">", LEGEND_HEIGHT).unwrap();
+    svg.push_str(&percent_chart(game, percents));
+    writeln!(svg, "</svg>\n<svg y=\"{}\">", LEGEND_HEIGHT + percent_height + 8).unwrap();
+    svg.push_str(&stock_timeline(game, frames));
+    writeln!(svg, "</svg>\n</svg>").unwrap();
+    svg
+}
+
+/// A line per player of their percent over the whole game. Conversions are shaded in the
+/// attacker's color, and each stock loss is marked with a cross at the percent it happened at.
+pub fn percent_chart(game: &AnalyzedGame, percents: &[Vec<f32>]) -> String {
+    let frames = percents.first().map_or(0, |p| p.len());
+    let highest = percents.iter().flatten().fold(0.0_f32, |a, b| a.max(*b));
+    // Round the top of the chart up to a multiple of 50%, and show at least up to 100%.
+    let top = ((highest / 50.0).ceil() * 50.0).max(100.0);
+    let plot_width = CHART_WIDTH as f32 - MARGIN_LEFT;
+    let x = |frame: usize| MARGIN_LEFT + frame as f32 * plot_width / frames.max(1) as f32;
+    let y = |percent: f32| CHART_HEIGHT as f32 * (1.0 - percent / top) + 1.0;
+    let mut svg = String::new();
+
+    writeln!(
+        svg,
+        "<svg width=\"{}\" height=\"{}\" font-size=\"11\">",
+        CHART_WIDTH,
+        CHART_HEIGHT as f32 + MARGIN_BOTTOM
+    )
+    .unwrap();
+
+    for conversion in &game.conversions {
+        let attacker = conversion.adv_index.and_then(|i| game.info.players.get(i));
+        let end = conversion.end_frame.unwrap_or(conversion.start_frame);
+        writeln!(
+            svg,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{}\" fill=\"{}\" opacity=\"0.15\"><title>{} at {}: {:.0}% in {} hits</title></rect>",
+            x(conversion.start_frame),
+            y(top),
+            (x(end) - x(conversion.start_frame)).max(1.0),
+            CHART_HEIGHT,
+            attacker.map_or("#888", |p| color(p.port)),
+            attacker.map_or("Unknown".to_string(), |p| escape(&p.name())),
+            game_time(conversion.start_frame),
+            conversion.damage(),
+            conversion.attacks.len()
+        )
+        .unwrap();
+    }
+
+    let mut gridline = 0.0;
+    while gridline <= top {
+        writeln!(
+            svg,
+            "<line x1=\"{}\" x2=\"{}\" y1=\"{:.1}\" y2=\"{:.1}\" stroke=\"#ddd\"/><text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}%</text>",
+            MARGIN_LEFT,
+            CHART_WIDTH,
+            y(gridline),
+            y(gridline),
+            MARGIN_LEFT - 4.0,
+            y(gridline) + 4.0,
+            gridline
+        )
+        .unwrap();
+        gridline += 50.0;
+    }
+
+    let go = -FIRST_FRAME_INDEX as usize;
+    for frame in (go..frames).step_by(TIME_LABEL_SECONDS * 60) {
+        writeln!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>",
+            x(frame),
+            CHART_HEIGHT as f32 + MARGIN_BOTTOM - 4.0,
+            game_time(frame)
+        )
+        .unwrap();
+    }
+
+    for (player, percents) in game.info.players.iter().zip(percents) {
+        let points = percents
+            .iter()
+            .enumerate()
+            .step_by(CHART_STEP)
+            .map(|(frame, percent)| format!("{:.1},{:.1}", x(frame), y(*percent)))
+            .collect::<Vec<String>>()
+            .join(" ");
+        writeln!(
+            svg,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>",
+            points,
+            color(player.port)
+        )
+        .unwrap();
+    }
+
+    for stock in &game.stocks_lost {
+        let player = match game.info.players.get(stock.player_index) {
+            Some(player) => player,
+            None => continue,
+        };
+        let percent = percents.get(stock.player_index).and_then(|p| p.get(stock.frame)).copied().unwrap_or(0.0);
+        let (cx, cy) = (x(stock.frame), y(percent));
+        writeln!(
+            svg,
+            "<path d=\"M{:.1},{:.1} l8,8 m0,-8 l-8,8\" stroke=\"{}\" stroke-width=\"2.5\"><title>{} lost a stock at {:.0}% ({})</title></path>",
+            cx - 4.0,
+            cy - 4.0,
+            color(player.port),
+            escape(&player.name()),
+            percent,
+            game_time(stock.frame)
+        )
+        .unwrap();
+    }
+
+    writeln!(svg, "</svg>").unwrap();
+    svg
+}
+
+/// One row per player, with a block for each stock from when it started to when it was lost.
+pub fn stock_timeline(game: &AnalyzedGame, frames: usize) -> String {
+    let players = game.info.players.len();
+    let plot_width = CHART_WIDTH as f32 - MARGIN_LEFT;
+    let x = |frame: usize| MARGIN_LEFT + frame as f32 * plot_width / frames.max(1) as f32;
+    let mut svg = String::new();
+
+    writeln!(
+        svg,
+        "<svg width=\"{}\" height=\"{}\">",
+        CHART_WIDTH,
+        players * STOCK_ROW_HEIGHT
+    )
+    .unwrap();
+
+    for (i, player) in game.info.players.iter().enumerate() {
+        let y = i * STOCK_ROW_HEIGHT;
+        let mut start = 0;
+        let mut losses: Vec<usize> = game.stocks_lost.iter().filter(|s| s.player_index == i).map(|s| s.frame).collect();
+        // Whatever stock they were on at the end lasted the rest of the game.
+        if player.final_stocks > 0 {
+            losses.push(frames);
+        }
+        for (stock, end) in losses.into_iter().enumerate() {
+            writeln!(
+                svg,
+                "<rect x=\"{:.1}\" y=\"{}\" width=\"{:.1}\" height=\"{}\" fill=\"{}\" stroke=\"#fff\" opacity=\"{}\"><title>Stock {}: {} - {}</title></rect>",
+                x(start),
+                y + 2,
+                x(end) - x(start),
+                STOCK_ROW_HEIGHT - 4,
+                color(player.port),
+                if stock % 2 == 0 { "1" } else { "0.7" },
+                stock + 1,
+                game_time(start),
+                game_time(end)
+            )
+            .unwrap();
+            start = end;
+        }
+    }
+    writeln!(svg, "</svg>").unwrap();
+    svg
+}
+
+/// In-game time for a frame (counted from the first frame like `Conversion::start_frame`), e.g. `1:05`.
+pub fn game_time(frame: usize) -> String {
+    let seconds = frame.saturating_sub(-FIRST_FRAME_INDEX as usize) / 60;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+pub fn color(port: Port) -> &'static str {
+    PLAYER_COLORS[u8::from(port) as usize]
+}
+
+/// Escapes text for SVG and HTML.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::StockLoss;
+    use crate::testing::{self, player};
+    use peppi::model::enums::character::External;
+
+    #[test]
+    fn game_time_starts_at_go() {
+        assert_eq!(game_time(0), "0:00");
+        assert_eq!(game_time(122), "0:00");
+        assert_eq!(game_time(123 + 59), "0:00");
+        assert_eq!(game_time(123 + 60), "0:01");
+        assert_eq!(game_time(123 + 65 * 60), "1:05");
+    }
+
+    #[test]
+    fn last_stock_lasts_until_the_end() {
+        let mut players = vec![player(Port::P1, External::FOX, None), player(Port::P2, External::MARTH, None)];
+        players[0].final_stocks = 2;
+        let mut game = testing::game(players, Vec::new());
+        let loss = |player_index, frame| StockLoss { player_index, frame };
+        game.stocks_lost = vec![loss(0, 1323), loss(1, 2523), loss(1, 3000)];
+        game.stocks_lost.extend([loss(1, 3723), loss(0, 3900), loss(1, 4000)]);
+
+        let svg = stock_timeline(&game, 4323);
+        let titles: Vec<&str> = svg.split("<title>").skip(1).map(|t| t.split("</title>").next().unwrap()).collect();
+        assert_eq!(
+            titles,
+            [
+                "Stock 1: 0:00 - 0:20",
+                "Stock 2: 0:20 - 1:02",
+                "Stock 3: 1:02 - 1:10",
+                "Stock 1: 0:00 - 0:40",
+                "Stock 2: 0:40 - 0:47",
+                "Stock 3: 0:47 - 1:00",
+                "Stock 4: 1:00 - 1:04",
+            ]
+        );
+    }
+
+    #[test]
+    fn stock_losses_without_percents_are_at_zero() {
+        let mut game = testing::game(vec![player(Port::P1, External::FOX, None)], Vec::new());
+        game.stocks_lost = vec![StockLoss { player_index: 0, frame: 500 }];
+        assert!(percent_chart(&game, &[]).contains("lost a stock at 0%"));
+    }
+}
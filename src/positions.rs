@@ -0,0 +1,290 @@
+use crate::frameinfo::{get_stage_string, PlayerFrame};
+use crate::identity::Aliases;
+use crate::info::GameInfo;
+use crate::plot::escape;
+use crate::stages::{self, BlastZones, StageZone};
+use core::fmt::{self, Display, Write};
+use peppi::model::enums::action_state::Common;
+use peppi::model::enums::stage::Stage;
+use peppi::model::frame::Frame;
+use peppi::model::game::{Frames, Game};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Size of a heatmap cell, in game units.
+pub const CELL_SIZE: f32 = 5.0;
+/// The grid covers the blast zones, or this on stages we don't know the geometry of.
+const DEFAULT_BOUNDS: BlastZones = BlastZones { left: -250.0, right: 250.0, top: 250.0, bottom: -150.0 };
+/// SVG pixels per game unit.
+const SVG_SCALE: f32 = 2.0;
+
+/// Where one player spent their time on one stage: a grid of frames spent in each cell, and
+/// frames spent in each stage zone.
+#[derive(Clone, Debug, Serialize)]
+pub struct Heatmap {
+    pub stage: Stage,
+    /// Who they are, as given by `Aliases::identity`.
+    pub player: String,
+    /// Position of the grid's bottom left corner.
+    pub left: f32,
+    pub bottom: f32,
+    pub columns: usize,
+    pub rows: usize,
+    /// Frames spent in each cell, a row at a time from the bottom. Positions outside the grid aren't counted.
+    pub cells: Vec<u32>,
+    pub frames: usize,
+    pub zone_frames: HashMap<StageZone, usize>,
+}
+
+impl Heatmap {
+    fn new(stage: Stage, player: String) -> Heatmap {
+        let bounds = stages::geometry(stage).map_or(DEFAULT_BOUNDS, |g| g.blast_zones);
+        let columns = ((bounds.right - bounds.left) / CELL_SIZE).ceil() as usize;
+        let rows = ((bounds.top - bounds.bottom) / CELL_SIZE).ceil() as usize;
+        Heatmap {
+            stage,
+            player,
+            left: bounds.left,
+            bottom: bounds.bottom,
+            columns,
+            rows,
+            cells: vec![0; columns * rows],
+            frames: 0,
+            zone_frames: HashMap::new(),
+        }
+    }
+
+    fn add(&mut self, x: f32, y: f32) {
+        self.frames += 1;
+        if let Some(zone) = stages::zone(self.stage, x, y) {
+            *self.zone_frames.entry(zone).or_default() += 1;
+        }
+        let column = ((x - self.left) / CELL_SIZE).floor();
+        let row = ((y - self.bottom) / CELL_SIZE).floor();
+        if column >= 0.0 && row >= 0.0 && (column as usize) < self.columns && (row as usize) < self.rows {
+            self.cells[row as usize * self.columns + column as usize] += 1;
+        }
+    }
+
+    /// Share of their time spent in `zone`, from 0 to 1.
+    pub fn zone_share(&self, zone: StageZone) -> f32 {
+        self.zone_frames.get(&zone).copied().unwrap_or(0) as f32 / self.frames.max(1) as f32
+    }
+
+    /// The heatmap as a standalone SVG, with the stage drawn on top.
+    pub fn svg(&self) -> String {
+        let width = self.columns as f32 * CELL_SIZE * SVG_SCALE;
+        let height = self.rows as f32 * CELL_SIZE * SVG_SCALE;
+        let x = |x: f32| (x - self.left) * SVG_SCALE;
+        let y = |y: f32| height - (y - self.bottom) * SVG_SCALE;
+        let most = self.cells.iter().copied().max().unwrap_or(0).max(1) as f32;
+        let cell = CELL_SIZE * SVG_SCALE;
+        let mut svg = String::new();
+
+        writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"sans-serif\">",
+            width,
+            height
+        )
+        .unwrap();
+        writeln!(svg, "<rect width=\"100%\" height=\"100%\" fill=\"#fff\"/>").unwrap();
+        for (i, frames) in self.cells.iter().enumerate().filter(|(_, f)| **f > 0) {
+            let (row, column) = (i / self.columns, i % self.columns);
+            // Square root so the rest of the stage still shows up next to where they stood around the most.
+            writeln!(
+                svg,
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{}\" height=\"{}\" fill=\"#e54b4b\" opacity=\"{:.2}\"/>",
+                column as f32 * cell,
+                height - (row + 1) as f32 * cell,
+                cell,
+                cell,
+                (*frames as f32 / most).sqrt()
+            )
+            .unwrap();
+        }
+
+        if let Some(geometry) = stages::geometry(self.stage) {
+            let [(left_x, left_y), (right_x, right_y)] = geometry.ledges();
+            writeln!(
+                svg,
+                "<line x1=\"{:.1}\" x2=\"{:.1}\" y1=\"{:.1}\" y2=\"{:.1}\" stroke=\"#222\" stroke-width=\"3\"/>",
+                x(left_x),
+                x(right_x),
+                y(left_y),
+                y(right_y)
+            )
+            .unwrap();
+            for platform in geometry.platforms {
+                // Moving platforms get a box over the heights they move between.
+                writeln!(
+                    svg,
+                    "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"#222\" fill-opacity=\"0.1\" stroke=\"#222\" stroke-width=\"2\"/>",
+                    x(platform.left),
+                    y(platform.highest),
+                    (platform.right - platform.left) * SVG_SCALE,
+                    (platform.highest - platform.lowest) * SVG_SCALE
+                )
+                .unwrap();
+            }
+            for transformation in geometry.transformations {
+                for platform in transformation.platforms {
+                    writeln!(
+                        svg,
+                        "<line x1=\"{:.1}\" x2=\"{:.1}\" y1=\"{:.1}\" y2=\"{:.1}\" stroke=\"#888\" stroke-dasharray=\"4\"><title>{}</title></line>",
+                        x(platform.left),
+                        x(platform.right),
+                        y(platform.lowest),
+                        y(platform.lowest),
+                        transformation.name
+                    )
+                    .unwrap();
+                }
+            }
+        }
+
+        writeln!(
+            svg,
+            "<text x=\"8\" y=\"18\" font-size=\"14\">{} on {}</text>\n</svg>",
+            escape(&self.player),
+            get_stage_string(self.stage)
+        )
+        .unwrap();
+        svg
+    }
+}
+
+/// Heatmaps for every player on every stage, over any number of games.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Positions {
+    pub heatmaps: Vec<Heatmap>,
+}
+
+impl Positions {
+    /// Adds every frame of `game` from "Go!" on, except while players are dead or respawning.
+    /// Players are keyed by identity like in `PlayerIndex`, so anonymous players are left out.
+    pub fn add_game(&mut self, game: &Game, aliases: &Aliases) {
+        let info = GameInfo::new(game);
+        match &game.frames {
+            Frames::P1(f) => self.add_frames(&info, aliases, f),
+            Frames::P2(f) => self.add_frames(&info, aliases, f),
+            Frames::P3(f) => self.add_frames(&info, aliases, f),
+            Frames::P4(f) => self.add_frames(&info, aliases, f),
+        }
+    }
+
+    fn add_frames<const N: usize>(&mut self, info: &GameInfo, aliases: &Aliases, frames: &[Frame<N>]) {
+        for (port, player) in info.players.iter().enumerate() {
+            let name = match aliases.identity(player) {
+                Some(name) => name,
+                None => continue,
+            };
+            let index = match self.heatmaps.iter().position(|h| h.stage == info.stage && h.player == name) {
+                Some(index) => index,
+                None => {
+                    self.heatmaps.push(Heatmap::new(info.stage, name));
+                    self.heatmaps.len() - 1
+                }
+            };
+            let heatmap = &mut self.heatmaps[index];
+            for frame in frames.iter().filter(|f| f.index >= 0) {
+                let player_frame = &frame.ports[port];
+                if player_frame.action_state_id() <= Common::REBIRTH_WAIT.0 {
+                    continue;
+                }
+                let position = player_frame.leader.post.position;
+                heatmap.add(position.x, position.y);
+            }
+        }
+    }
+}
+
+impl Display for Positions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut by_stage: BTreeMap<String, Vec<&Heatmap>> = BTreeMap::new();
+        for heatmap in &self.heatmaps {
+            by_stage.entry(get_stage_string(heatmap.stage)).or_default().push(heatmap);
+        }
+        for (stage, heatmaps) in by_stage {
+            writeln!(f, "{}", stage)?;
+            for heatmap in heatmaps {
+                if heatmap.zone_frames.is_empty() {
+                    writeln!(f, "   {}: {} seconds (no stage data)", heatmap.player, heatmap.frames / 60)?;
+                    continue;
+                }
+                writeln!(
+                    f,
+                    "   {}: {:.0}% center, {:.0}% near the ledge, {:.0}% offstage, {:.0}% on platforms ({} seconds)",
+                    heatmap.player,
+                    heatmap.zone_share(StageZone::Center) * 100.0,
+                    heatmap.zone_share(StageZone::Ledge) * 100.0,
+                    heatmap.zone_share(StageZone::Offstage) * 100.0,
+                    heatmap.zone_share(StageZone::Platform) * 100.0,
+                    heatmap.frames / 60
+                )?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, player, standing};
+    use peppi::model::enums::action_state::State;
+    use peppi::model::enums::character::External;
+    use peppi::model::primitives::Port;
+
+    #[test]
+    fn counts_frames_in_each_cell() {
+        let mut heatmap = Heatmap::new(Stage::BATTLEFIELD, "Fox".to_string());
+        let (left, bottom) = (heatmap.left, heatmap.bottom);
+        heatmap.add(left + 7.0, bottom + 12.0);
+        heatmap.add(left + 9.9, bottom + 10.0);
+        assert_eq!(heatmap.cells[2 * heatmap.columns + 1], 2);
+
+        // Past the blast zones, so only the total goes up.
+        heatmap.add(left - 1.0, 0.0);
+        heatmap.add(0.0, bottom + heatmap.rows as f32 * CELL_SIZE);
+        assert_eq!(heatmap.frames, 4);
+        assert_eq!(heatmap.cells.iter().sum::<u32>(), 2);
+    }
+
+    #[test]
+    fn skips_frames_before_go_and_while_dead() {
+        let info = testing::game(vec![player(Port::P1, External::FOX, Some("ABCD#123"))], Vec::new()).info;
+        let frames: Vec<Frame<1>> = (0..300)
+            .map(|i| {
+                let mut port = standing(0.0);
+                port.leader.post.state = State::Common(match i {
+                    200..220 => Common::DEAD_DOWN,
+                    220..240 => Common::REBIRTH,
+                    240..250 => Common::REBIRTH_WAIT,
+                    _ => Common::WAIT,
+                });
+                testing::frame(i, [port])
+            })
+            .collect();
+
+        let mut positions = Positions::default();
+        positions.add_frames(&info, &Aliases::default(), &frames);
+        // 123 frames before Go and 50 dead or respawning.
+        assert_eq!(positions.heatmaps[0].frames, 300 - 123 - 50);
+        assert_eq!(positions.heatmaps[0].player, "ABCD#123");
+    }
+
+    #[test]
+    fn shows_total_time() {
+        let mut heatmap = Heatmap::new(Stage::BATTLEFIELD, "Fox".to_string());
+        for _ in 0..120 {
+            heatmap.add(0.0, 0.0);
+        }
+        let mut unknown = Heatmap::new(Stage::PRINCESS_PEACHS_CASTLE, "Fox".to_string());
+        unknown.add(0.0, 0.0);
+        let text = Positions { heatmaps: vec![heatmap, unknown] }.to_string();
+        assert!(text.contains("100% center, 0% near the ledge, 0% offstage, 0% on platforms (2 seconds)"), "{}", text);
+        assert!(text.contains("Fox: 0 seconds (no stage data)"), "{}", text);
+    }
+}
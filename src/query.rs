@@ -0,0 +1,359 @@
+use crate::detector::{Conversion, PlayerAttack};
+use crate::frameinfo::{get_character_string, get_stage_string};
+use crate::info::PlayerInfo;
+use crate::library::AnalyzedGame;
+use core::fmt::{self, Display};
+
+/// A search over conversions, written as space separated terms that all have to match.
+/// Names are case insensitive.
+///
+/// - `attacker:FOX`, `defender:ABCD#123`: a player's character, connect code, netplay name or tag
+/// - `stage:BATTLEFIELD`, `opening:counter-attack`
+/// - `moves:UP_THROW>UAIR`: these moves in this order, with anything in between
+/// - `opener:GRAB`, `finisher:UAIR`: the first or last move
+/// - `kill` (or `kill:no`)
+/// - `dropped` (or `dropped:no`): the attacker had a chance to follow up but let it go (see `Conversion::missed_follow_up`)
+/// - `hits>=4`, `damage>40`, `start%<30`, `end%>=100`: compare with `<`, `<=`, `>`, `>=` or `=`
+///
+/// In move names, `GRAB` also matches throws and pummels, and `THROW` means any throw.
+///
+/// e.g. `attacker:FOX moves:UP_THROW>UAIR kill`, or `hits>=4 opener:GRAB start%<30`.
+#[derive(Clone, Debug)]
+pub struct Query {
+    terms: Vec<Term>,
+}
+
+#[derive(Clone, Debug)]
+enum Term {
+    Attacker(String),
+    Defender(String),
+    Stage(String),
+    Opening(String),
+    Moves(Vec<String>),
+    Opener(String),
+    Finisher(String),
+    Kill(bool),
+    Dropped(bool),
+    Compare(Field, Op, f32),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Field {
+    Hits,
+    Damage,
+    StartPercent,
+    EndPercent,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+}
+
+#[derive(Debug)]
+pub struct QueryError(pub String);
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad query: {}", self.0)
+    }
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Query, QueryError> {
+        let terms = query.split_whitespace().map(parse_term).collect::<Result<Vec<Term>, QueryError>>()?;
+        Ok(Query { terms })
+    }
+
+    pub fn matches(&self, game: &AnalyzedGame, conversion: &Conversion) -> bool {
+        self.terms.iter().all(|term| term_matches(term, game, conversion))
+    }
+}
+
+fn parse_term(term: &str) -> Result<Term, QueryError> {
+    if term.eq_ignore_ascii_case("kill") {
+        return Ok(Term::Kill(true));
+    }
+    if term.eq_ignore_ascii_case("dropped") {
+        return Ok(Term::Dropped(true));
+    }
+    if let Some((name, value)) = term.split_once(':') {
+        let value = value.to_uppercase();
+        return match name.to_lowercase().as_str() {
+            "attacker" => Ok(Term::Attacker(value)),
+            "defender" => Ok(Term::Defender(value)),
+            "stage" => Ok(Term::Stage(value)),
+            "opening" => Ok(Term::Opening(value)),
+            "moves" => Ok(Term::Moves(value.split('>').map(str::to_string).collect())),
+            "opener" => Ok(Term::Opener(value)),
+            "finisher" => Ok(Term::Finisher(value)),
+            "kill" => yes_or_no(name, &value).map(Term::Kill),
+            "dropped" => yes_or_no(name, &value).map(Term::Dropped),
+            _ => Err(QueryError(format!("unknown term {}", name))),
+        };
+    }
+
+    let split = term
+        .find(['<', '>', '='])
+        .ok_or_else(|| QueryError(format!("don't know what {} means", term)))?;
+    let (field, rest) = term.split_at(split);
+    let field = match field.to_lowercase().trim_end_matches('%') {
+        "hits" => Field::Hits,
+        "damage" => Field::Damage,
+        "start" => Field::StartPercent,
+        "end" => Field::EndPercent,
+        _ => return Err(QueryError(format!("can't compare {}", field))),
+    };
+    let (op, value) = [("<=", Op::Le), (">=", Op::Ge), ("<", Op::Lt), (">", Op::Gt), ("=", Op::Eq)]
+        .iter()
+        .find_map(|(symbol, op)| rest.strip_prefix(symbol).map(|value| (*op, value)))
+        .unwrap();
+    let value = value
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| QueryError(format!("{} isn't a number", value)))?;
+    Ok(Term::Compare(field, op, value))
+}
+
+fn yes_or_no(name: &str, value: &str) -> Result<bool, QueryError> {
+    match value {
+        "YES" | "TRUE" => Ok(true),
+        "NO" | "FALSE" => Ok(false),
+        _ => Err(QueryError(format!("{} should be yes or no, not {}", name, value))),
+    }
+}
+
+fn term_matches(term: &Term, game: &AnalyzedGame, conversion: &Conversion) -> bool {
+    let player = |i: Option<usize>| i.and_then(|i| game.info.players.get(i));
+    match term {
+        Term::Attacker(name) => player(conversion.adv_index).is_some_and(|p| is_player(p, name)),
+        Term::Defender(name) => player(Some(conversion.disadv_index)).is_some_and(|p| is_player(p, name)),
+        Term::Stage(stage) => get_stage_string(game.info.stage) == *stage,
+        Term::Opening(opening) => conversion.opening_type.as_deref().is_some_and(|o| o.eq_ignore_ascii_case(opening)),
+        Term::Moves(moves) => contains_in_order(&conversion.attacks, moves),
+        Term::Opener(name) => conversion.attacks.first().is_some_and(|a| is_move(a, name)),
+        Term::Finisher(name) => conversion.attacks.last().is_some_and(|a| is_move(a, name)),
+        Term::Kill(kill) => conversion.did_kill == *kill,
+        Term::Dropped(dropped) => conversion.missed_follow_up.is_some() == *dropped,
+        Term::Compare(field, op, value) => {
+            let actual = match field {
+                Field::Hits => conversion.attacks.len() as f32,
+                Field::Damage => conversion.damage(),
+                Field::StartPercent => conversion.start_percent,
+                Field::EndPercent => conversion.end_percent.unwrap_or(conversion.start_percent),
+            };
+            match op {
+                Op::Lt => actual < *value,
+                Op::Le => actual <= *value,
+                Op::Gt => actual > *value,
+                Op::Ge => actual >= *value,
+                Op::Eq => actual == *value,
+            }
+        }
+    }
+}
+
+fn is_player(player: &PlayerInfo, name: &str) -> bool {
+    get_character_string(player.character) == name || player.names().iter().any(|n| n.eq_ignore_ascii_case(name))
+}
+
+fn is_move(attack: &PlayerAttack, name: &str) -> bool {
+    let attack = attack.name();
+    match name {
+        "GRAB" => attack == "GRAB" || attack.ends_with("THROW") || attack == "PUMMEL",
+        "THROW" => attack.ends_with("THROW"),
+        _ => attack == name,
+    }
+}
+
+/// Whether `moves` show up in `attacks` in order, not necessarily next to each other.
+fn contains_in_order(attacks: &[PlayerAttack], moves: &[String]) -> bool {
+    let mut attacks = attacks.iter();
+    moves.iter().all(|name| attacks.any(|a| is_move(a, name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detector::{FrameWindow, Location};
+    use crate::info::GameInfo;
+    use crate::rollback::RollbackStats;
+    use peppi::model::enums::attack::Attack;
+    use peppi::model::enums::character::External;
+    use peppi::model::enums::stage::Stage;
+    use peppi::model::game::EndMethod;
+    use peppi::model::primitives::Port;
+    use peppi::model::slippi::Version;
+    use std::path::PathBuf;
+
+    fn player(port: Port, character: External, connect_code: Option<&str>) -> PlayerInfo {
+        PlayerInfo {
+            port,
+            character,
+            costume: 0,
+            tag: None,
+            netplay_name: None,
+            connect_code: connect_code.map(str::to_string),
+            final_stocks: 0,
+            final_percent: 0.0,
+        }
+    }
+
+    /// Fox (`ABCD#123`) against an anonymous Jigglypuff on Battlefield.
+    fn game() -> AnalyzedGame {
+        AnalyzedGame {
+            path: PathBuf::from("game.slp"),
+            info: GameInfo {
+                stage: Stage::BATTLEFIELD,
+                players: vec![player(Port::P1, External::FOX, Some("ABCD#123")), player(Port::P2, External::JIGGLYPUFF, None)],
+                duration: 3600,
+                end_method: EndMethod::GAME,
+                lras_initiator: None,
+                winners: vec![Port::P1],
+                slippi_version: Version(3, 12, 0),
+                date: None,
+                platform: None,
+                console: None,
+            },
+            conversions: Vec::new(),
+            stocks_lost: Vec::new(),
+            rollbacks: RollbackStats::default(),
+        }
+    }
+
+    fn location() -> Location {
+        Location { x: 0.0, y: 0.0, velocity: None, zone: None }
+    }
+
+    fn attack(attack: Option<Attack>, grab: bool) -> PlayerAttack {
+        PlayerAttack {
+            player_index: Some(0),
+            attack,
+            frame: 0,
+            grab,
+            item: None,
+            defender_percent: 0.0,
+            hitstun: None,
+            attacker_location: None,
+            di: None,
+        }
+    }
+
+    /// Fox grabs, up throws and up airs Jigglypuff from 20% to 60% without killing.
+    fn conversion() -> Conversion {
+        Conversion {
+            adv_index: Some(0),
+            disadv_index: 1,
+            has_been_grounded_actionable: false,
+            frames_since_last_hit: 0,
+            actionable_frames: 0,
+            start_frame: 100,
+            end_frame: Some(200),
+            start_percent: 20.0,
+            end_percent: Some(60.0),
+            start_location: location(),
+            end_location: Some(location()),
+            attacks: vec![attack(None, true), attack(Some(Attack::UP_THROW), false), attack(Some(Attack::UAIR), false)],
+            did_kill: false,
+            opening_type: Some("neutral-win".to_string()),
+            follow_up_window: None,
+            missed_follow_up: None,
+        }
+    }
+
+    fn matches(query: &str, conversion: &Conversion) -> bool {
+        Query::parse(query).unwrap().matches(&game(), conversion)
+    }
+
+    fn error(query: &str) -> String {
+        Query::parse(query).unwrap_err().0
+    }
+
+    #[test]
+    fn matches_players_by_character_or_name() {
+        let conversion = conversion();
+        assert!(matches("attacker:FOX", &conversion));
+        assert!(matches("attacker:abcd#123 defender:jigglypuff", &conversion));
+        assert!(!matches("attacker:JIGGLYPUFF", &conversion));
+        assert!(!matches("defender:ABCD#123", &conversion));
+    }
+
+    #[test]
+    fn matches_stage_and_opening() {
+        let conversion = conversion();
+        assert!(matches("stage:battlefield opening:NEUTRAL-WIN", &conversion));
+        assert!(!matches("stage:DREAMLAND_N64", &conversion));
+        assert!(!matches("opening:counter-attack", &conversion));
+    }
+
+    #[test]
+    fn matches_moves_in_order() {
+        let conversion = conversion();
+        assert!(matches("moves:UP_THROW>UAIR", &conversion));
+        assert!(matches("moves:grab>uair", &conversion));
+        assert!(!matches("moves:UAIR>UP_THROW", &conversion));
+        assert!(!matches("moves:UP_THROW>UAIR>UAIR", &conversion));
+    }
+
+    #[test]
+    fn grab_and_throw_cover_every_throw() {
+        let conversion = conversion();
+        assert!(matches("opener:GRAB finisher:UAIR", &conversion));
+        assert!(matches("moves:THROW", &conversion));
+        assert!(!matches("opener:THROW", &conversion));
+        assert!(!matches("finisher:GRAB", &conversion));
+
+        let mut pummeled = conversion.clone();
+        pummeled.attacks = vec![attack(Some(Attack::PUMMEL), false)];
+        assert!(matches("opener:GRAB", &pummeled));
+        assert!(!matches("opener:THROW", &pummeled));
+    }
+
+    #[test]
+    fn matches_kills_and_dropped_follow_ups() {
+        let mut conversion = conversion();
+        assert!(matches("kill:no dropped:false", &conversion));
+        assert!(!matches("kill", &conversion));
+
+        conversion.did_kill = true;
+        conversion.missed_follow_up = Some(FrameWindow { start: 150, end: 170 });
+        assert!(matches("KILL dropped:yes", &conversion));
+        assert!(!matches("kill:no", &conversion));
+        assert!(!matches("dropped:no", &conversion));
+    }
+
+    #[test]
+    fn compares_numbers() {
+        let mut conversion = conversion();
+        assert!(matches("hits>=3 hits<=3 hits=3", &conversion));
+        assert!(!matches("hits>=4", &conversion));
+        assert!(matches("start%<30 end%>=60 damage>39.5", &conversion));
+        assert!(matches("start%<30%", &conversion));
+        assert!(!matches("damage>40", &conversion));
+
+        // Still in progress: no damage yet, and it ends where it started.
+        conversion.end_percent = None;
+        assert!(matches("damage=0 end%=20", &conversion));
+    }
+
+    #[test]
+    fn rejects_bad_terms() {
+        assert_eq!(error("kill:maybe"), "kill should be yes or no, not MAYBE");
+        assert_eq!(error("winner:FOX"), "unknown term winner");
+        assert_eq!(error("uair"), "don't know what uair means");
+        assert_eq!(error("combo>3"), "can't compare combo");
+        assert_eq!(error("hits>=four"), "four isn't a number");
+    }
+
+    #[test]
+    fn every_term_has_to_match() {
+        let conversion = conversion();
+        assert!(matches("", &conversion));
+        assert!(matches("attacker:FOX  hits>=3", &conversion));
+        assert!(!matches("attacker:FOX hits>=4", &conversion));
+    }
+}
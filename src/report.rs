@@ -0,0 +1,199 @@
+use crate::detector::Location;
+use crate::frameinfo::{get_character_string, get_stage_string};
+use crate::library::AnalyzedGame;
+use crate::plot::{self, color, escape, game_time};
+use crate::stats::ConversionStats;
+use core::fmt::Write;
+use peppi::model::primitives::Port;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em auto; max-width: 900px; color: #222; }
+h1 { font-size: 1.5em; }
+table { border-collapse: collapse; margin-bottom: 1.5em; }
+th, td { padding: 0.3em 0.8em; border-bottom: 1px solid #ddd; text-align: left; }
+td.number { text-align: right; }
+svg { display: block; margin-bottom: 1.5em; }
+svg text { font-size: 11px; }
+details summary { cursor: pointer; }
+.swatch { display: inline-block; width: 0.8em; height: 0.8em; margin-right: 0.4em; }
+";
+
+/// A self-contained HTML page for one game: who played, stock and percent timelines, neutral stats
+/// and every conversion. `percents` is each player's percent on every frame (see `library::percent_timeline`).
+pub fn render(game: &AnalyzedGame, percents: &[Vec<f32>]) -> String {
+    let info = &game.info;
+    let title = format!("{} on {}", info.matchup(), get_stage_string(info.stage));
+    let mut html = String::new();
+
+    writeln!(html, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">").unwrap();
+    writeln!(html, "<title>{}</title>\n<style>{}</style>\n</head>\n<body>", escape(&title), STYLE).unwrap();
+    writeln!(html, "<h1>{}</h1>", escape(&title)).unwrap();
+    summary(&mut html, game);
+
+    let stats = game.player_stats();
+    writeln!(html, "<h2>Players</h2>").unwrap();
+    players_table(&mut html, game, &stats);
+
+    let frames = percents.first().map_or(0, |p| p.len());
+    writeln!(html, "<h2>Stocks</h2>").unwrap();
+    html.push_str(&plot::stock_timeline(game, frames));
+    writeln!(html, "<h2>Percent</h2>").unwrap();
+    html.push_str(&plot::percent_chart(game, percents));
+
+    writeln!(html, "<h2>Neutral</h2>").unwrap();
+    neutral_table(&mut html, game, &stats);
+    writeln!(html, "<h2>Conversions</h2>").unwrap();
+    conversions_table(&mut html, game);
+
+    writeln!(html, "</body>\n</html>").unwrap();
+    html
+}
+
+fn summary(html: &mut String, game: &AnalyzedGame) {
+    let info = &game.info;
+    let seconds = info.duration / 60;
+    let winners = info
+        .winners
+        .iter()
+        .filter_map(|port| info.player(*port))
+        .map(|p| p.name())
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    writeln!(html, "<p>").unwrap();
+    if let Some(date) = info.date {
+        writeln!(html, "Played {}<br>", date.format("%Y-%m-%d %H:%M UTC")).unwrap();
+    }
+    writeln!(html, "{}:{:02}, ended by {}<br>", seconds / 60, seconds % 60, info.end_method_string()).unwrap();
+    match winners.is_empty() {
+        true => writeln!(html, "No winner").unwrap(),
+        _ => writeln!(html, "Won by {}", escape(&winners)).unwrap(),
+    }
+    writeln!(html, "</p>").unwrap();
+}
+
+fn players_table(html: &mut String, game: &AnalyzedGame, stats: &[ConversionStats]) {
+    writeln!(html, "<table>\n<tr><th>Player</th><th>Character</th><th>Stocks left</th><th>Final percent</th><th>Kills</th></tr>").unwrap();
+    for (player, stats) in game.info.players.iter().zip(stats) {
+        writeln!(
+            html,
+            "<tr><td>{}{}</td><td>{}</td><td class=\"number\">{}</td><td class=\"number\">{:.0}%</td><td class=\"number\">{}</td></tr>",
+            swatch(player.port),
+            escape(&player.name()),
+            get_character_string(player.character),
+            player.final_stocks,
+            player.final_percent,
+            stats.kills
+        )
+        .unwrap();
+    }
+    writeln!(html, "</table>").unwrap();
+}
+
+fn neutral_table(html: &mut String, game: &AnalyzedGame, stats: &[ConversionStats]) {
+    let total_openings: usize = stats.iter().map(|s| s.openings).sum();
+    writeln!(
+        html,
+        "<table>\n<tr><th>Player</th><th>Openings</th><th>Share</th><th>Neutral wins</th><th>Counter attacks</th>\
+         <th>Trades</th><th>Damage per opening</th><th>Openings per kill</th></tr>"
+    )
+    .unwrap();
+    for (player, stats) in game.info.players.iter().zip(stats) {
+        let share = match total_openings {
+            0 => 0.0,
+            n => stats.openings as f32 * 100.0 / n as f32,
+        };
+        let openings_per_kill = match stats.openings_per_kill() {
+            Some(n) => format!("{:.1}", n),
+            None => "-".to_string(),
+        };
+        writeln!(
+            html,
+            "<tr><td>{}{}</td><td class=\"number\">{}</td><td class=\"number\">{:.0}%</td><td class=\"number\">{}</td>\
+             <td class=\"number\">{}</td><td class=\"number\">{}</td><td class=\"number\">{:.1}</td><td class=\"number\">{}</td></tr>",
+            swatch(player.port),
+            escape(&player.name()),
+            stats.openings,
+            share,
+            stats.neutral_wins,
+            stats.counter_attacks,
+            stats.trades,
+            stats.damage_per_opening(),
+            openings_per_kill
+        )
+        .unwrap();
+    }
+    writeln!(html, "</table>").unwrap();
+}
+
+fn conversions_table(html: &mut String, game: &AnalyzedGame) {
+    let name = |i: Option<usize>| match i.and_then(|i| game.info.players.get(i)) {
+        Some(player) => escape(&player.name()),
+        None => "Unknown".to_string(),
+    };
+    writeln!(
+        html,
+        "<table>\n<tr><th>Time</th><th>Attacker</th><th>Defender</th><th>Opening</th><th>Where</th>\
+         <th>Percent</th><th>Damage</th><th>Kill</th><th>Moves</th></tr>"
+    )
+    .unwrap();
+    for conversion in &game.conversions {
+        let moves = conversion
+            .attacks
+            .iter()
+            .map(|a| format!("<li>{} <small>({})</small></li>", a.name(), game_time(a.frame)))
+            .collect::<String>();
+        let zone = |location: Option<&Location>| location.and_then(|l| l.zone).map_or("-", |z| z.name());
+        writeln!(
+            html,
+            "<tr><td>{} - {}</td><td>{}</td><td>{}</td><td>{}</td><td>{} - {}</td><td class=\"number\">{:.0}% - {:.0}%</td>\
+             <td class=\"number\">{:.1}</td><td>{}</td><td><details><summary>{} hits</summary><ol>{}</ol></details></td></tr>",
+            game_time(conversion.start_frame),
+            game_time(conversion.end_frame.unwrap_or(conversion.start_frame)),
+            name(conversion.adv_index),
+            name(Some(conversion.disadv_index)),
+            conversion.opening_type.as_deref().unwrap_or("-"),
+            zone(Some(&conversion.start_location)),
+            zone(conversion.end_location.as_ref()),
+            conversion.start_percent,
+            conversion.end_percent.unwrap_or(conversion.start_percent),
+            conversion.damage(),
+            if conversion.did_kill { "Yes" } else { "" },
+            conversion.attacks.len(),
+            moves
+        )
+        .unwrap();
+    }
+    writeln!(html, "</table>").unwrap();
+}
+
+fn swatch(port: Port) -> String {
+    format!("<span class=\"swatch\" style=\"background: {}\"></span>", color(port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detector::NEUTRAL_WIN;
+    use crate::testing::{attack, conversion, game, player};
+    use peppi::model::enums::attack::Attack;
+    use peppi::model::enums::character::External;
+
+    #[test]
+    fn escapes_player_names() {
+        let mut p1 = player(Port::P1, External::FOX, None);
+        p1.tag = Some("<b>&Co</b>".to_string());
+        let p2 = player(Port::P2, External::MARTH, Some("MA#1"));
+        let mut hit = conversion(0, 1, vec![attack(0, Some(Attack::FAIR), 0.0)], 12.0, false);
+        hit.opening_type = Some(NEUTRAL_WIN.to_string());
+        let mut game = game(vec![p1, p2], vec![hit]);
+        game.info.winners = vec![Port::P1];
+
+        let html = render(&game, &[vec![0.0; 300], vec![0.0; 300]]);
+        assert!(!html.contains("<b>&Co</b>"));
+        assert!(html.contains("Won by &lt;b&gt;&amp;Co&lt;/b&gt;"));
+        assert!(html.contains("&lt;b&gt;&amp;Co&lt;/b&gt;</td><td>FOX</td>"));
+        assert!(html.contains("<td>neutral-win</td>"));
+        assert!(html.contains("<li>FAIR <small>"));
+    }
+}
@@ -0,0 +1,258 @@
+use core::fmt::{self, Display};
+use peppi::model::frame::{self, Frame, Post, Pre};
+use peppi::model::game;
+use peppi::model::item::Item;
+use peppi::serde::collect::Collector;
+use peppi::serde::de::{FrameEvent, FrameId, Handlers, PortId};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::io;
+
+/// Netplay never rolls back further than this, so anything older is final even without `latest_finalized_frame`.
+const MAX_ROLLBACK_FRAMES: i32 = 7;
+
+/// How much netcode rollback happened during a game.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct RollbackStats {
+    /// Number of times the game rewound to an earlier frame.
+    pub rollbacks: usize,
+    /// Total frames that were simulated more than once.
+    pub frames_rolled_back: usize,
+    /// Most frames rewound by a single rollback.
+    pub longest_rollback: usize,
+
+    last_index: Option<i32>,
+    latest_index: Option<i32>,
+    /// Whether the replay has frame start events (Slippi 2.2 on), which come once per copy of a frame.
+    #[serde(skip)]
+    frame_starts: bool,
+}
+
+impl RollbackStats {
+    /// Records the frame start event for frame `index`.
+    pub fn record_frame_start(&mut self, index: i32) {
+        self.frame_starts = true;
+        self.record(index);
+    }
+
+    /// Records a leader's pre-frame event for frame `index`, which only counts for replays without
+    /// frame start events. There, repeats of the same index back to back (one per port) count once,
+    /// so a rollback of a single frame can't be told apart from the next port's data.
+    pub fn record_pre(&mut self, index: i32) {
+        if !self.frame_starts && self.last_index != Some(index) {
+            self.record(index);
+        }
+    }
+
+    /// Records that frame `index` was emitted (again).
+    fn record(&mut self, index: i32) {
+        if let Some(latest) = self.latest_index {
+            if index <= latest {
+                self.frames_rolled_back += 1;
+            }
+            if self.last_index.is_some_and(|last| index <= last) {
+                self.rollbacks += 1;
+                self.longest_rollback = self.longest_rollback.max((latest - index + 1) as usize);
+            }
+        }
+        self.last_index = Some(index);
+        self.latest_index = Some(self.latest_index.map_or(index, |latest| latest.max(index)));
+    }
+}
+
+impl Display for RollbackStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Rolled back {} frames over {} rollbacks (longest: {} frames)",
+            self.frames_rolled_back, self.rollbacks, self.longest_rollback
+        )
+    }
+}
+
+/// Holds back frames from a stream that may contain rollbacks until they can no longer change,
+/// so the detector only ever sees each frame once, in its final form.
+#[derive(Default)]
+pub struct RollbackFilter<const N: usize> {
+    pending: BTreeMap<i32, Frame<N>>,
+    latest_index: Option<i32>,
+    last_finalized: Option<i32>,
+}
+
+impl<const N: usize> RollbackFilter<N> {
+    /// Adds a frame (replacing any earlier version of it), then passes every frame that is now final to `f` in order.
+    pub fn push(&mut self, frame: Frame<N>, mut f: impl FnMut(&Frame<N>)) {
+        let index = frame.index;
+        let latest = self.latest_index.map_or(index, |latest| latest.max(index));
+        self.latest_index = Some(latest);
+
+        let finalized = frame
+            .end
+            .and_then(|end| end.latest_finalized_frame)
+            .unwrap_or(latest - MAX_ROLLBACK_FRAMES);
+        // A finalized frame has already been passed along, so it's too late to change it.
+        if self.last_finalized.is_none_or(|last| index > last) {
+            self.pending.insert(index, frame);
+        }
+
+        while let Some(entry) = self.pending.first_entry() {
+            if *entry.key() > finalized {
+                break;
+            }
+            self.last_finalized = Some(*entry.key());
+            f(&entry.remove());
+        }
+    }
+
+    /// Passes along every frame still being held back, for when the game is over.
+    pub fn finish(&mut self, mut f: impl FnMut(&Frame<N>)) {
+        while let Some((_, frame)) = self.pending.pop_first() {
+            f(&frame);
+        }
+    }
+}
+
+/// Collects a whole game like `peppi::game` (which keeps only the final version of each frame),
+/// while also recording rollback stats that would otherwise be lost.
+#[derive(Default)]
+pub struct RollbackCollector {
+    pub collector: Collector,
+    pub stats: RollbackStats,
+}
+
+impl Handlers for RollbackCollector {
+    fn gecko_codes(&mut self, codes: &[u8], actual_size: u16) -> io::Result<()> {
+        self.collector.gecko_codes(codes, actual_size)
+    }
+
+    fn game_start(&mut self, start: game::Start) -> io::Result<()> {
+        self.collector.game_start(start)
+    }
+
+    fn game_end(&mut self, end: game::End) -> io::Result<()> {
+        self.collector.game_end(end)
+    }
+
+    fn metadata(&mut self, metadata: Map<String, Value>) -> io::Result<()> {
+        self.collector.metadata(metadata)
+    }
+
+    fn frame_start(&mut self, evt: FrameEvent<FrameId, frame::Start>) -> io::Result<()> {
+        self.stats.record_frame_start(evt.id.index);
+        self.collector.frame_start(evt)
+    }
+
+    fn frame_pre(&mut self, evt: FrameEvent<PortId, Pre>) -> io::Result<()> {
+        if !evt.id.is_follower {
+            self.stats.record_pre(evt.id.index);
+        }
+        self.collector.frame_pre(evt)
+    }
+
+    fn frame_post(&mut self, evt: FrameEvent<PortId, Post>) -> io::Result<()> {
+        self.collector.frame_post(evt)
+    }
+
+    fn frame_end(&mut self, evt: FrameEvent<FrameId, frame::End>) -> io::Result<()> {
+        self.collector.frame_end(evt)
+    }
+
+    fn item(&mut self, evt: FrameEvent<FrameId, Item>) -> io::Result<()> {
+        self.collector.item(evt)
+    }
+
+    fn finalize(&mut self) -> io::Result<()> {
+        self.collector.finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A frame with no players, tagged with `version` so re-sent copies can be told apart.
+    fn frame(index: i32, version: u32, latest_finalized_frame: Option<i32>) -> Frame<0> {
+        Frame {
+            index,
+            ports: [],
+            start: Some(frame::Start { random_seed: version }),
+            end: Some(frame::End { latest_finalized_frame }),
+            items: None,
+        }
+    }
+
+    fn passed(filter: &mut RollbackFilter<0>, frame: Frame<0>) -> Vec<(i32, u32)> {
+        let mut passed = Vec::new();
+        filter.push(frame, |f| passed.push((f.index, f.start.unwrap().random_seed)));
+        passed
+    }
+
+    #[test]
+    fn stats_count_each_rollback() {
+        let mut stats = RollbackStats::default();
+        // Two ports per frame, then a rollback from frame 3 to frame 1.
+        for index in [0, 0, 1, 1, 2, 2, 3, 3, 1, 1, 2, 2, 3, 3, 4, 4] {
+            stats.record_pre(index);
+        }
+        assert_eq!(stats.rollbacks, 1);
+        assert_eq!(stats.frames_rolled_back, 3);
+        assert_eq!(stats.longest_rollback, 3);
+    }
+
+    #[test]
+    fn stats_without_rollbacks() {
+        let mut stats = RollbackStats::default();
+        for index in -123..100 {
+            stats.record_frame_start(index);
+            stats.record_pre(index);
+            stats.record_pre(index);
+        }
+        assert_eq!((stats.rollbacks, stats.frames_rolled_back, stats.longest_rollback), (0, 0, 0));
+    }
+
+    #[test]
+    fn stats_count_a_single_frame_rollback() {
+        let mut stats = RollbackStats::default();
+        // Frame 2 is simulated again right after itself, with two ports each time.
+        for index in [0, 1, 2, 2, 3] {
+            stats.record_frame_start(index);
+            stats.record_pre(index);
+            stats.record_pre(index);
+        }
+        assert_eq!((stats.rollbacks, stats.frames_rolled_back, stats.longest_rollback), (1, 1, 1));
+    }
+
+    #[test]
+    fn re_sent_frame_replaces_the_earlier_one() {
+        let mut filter = RollbackFilter::default();
+        let mut all = Vec::new();
+        for index in 0..5 {
+            all.extend(passed(&mut filter, frame(index, 0, None)));
+        }
+        // Without `latest_finalized_frame`, nothing is final until it's MAX_ROLLBACK_FRAMES old.
+        assert!(all.is_empty());
+
+        // Roll back to frame 2 and simulate it again.
+        for index in 2..20 {
+            all.extend(passed(&mut filter, frame(index, 1, None)));
+        }
+        filter.finish(|f| all.push((f.index, f.start.unwrap().random_seed)));
+
+        let expected: Vec<(i32, u32)> = (0..20).map(|index| (index, (index >= 2) as u32)).collect();
+        assert_eq!(all, expected);
+    }
+
+    #[test]
+    fn finalized_frames_pass_right_away() {
+        let mut filter = RollbackFilter::default();
+        assert_eq!(passed(&mut filter, frame(0, 0, Some(0))), vec![(0, 0)]);
+        assert!(passed(&mut filter, frame(1, 0, Some(0))).is_empty());
+        assert!(passed(&mut filter, frame(2, 0, Some(0))).is_empty());
+        assert_eq!(passed(&mut filter, frame(3, 0, Some(2))), vec![(1, 0), (2, 0)]);
+
+        // Frame 2 was already passed along, so a re-send of it is too late.
+        assert!(passed(&mut filter, frame(2, 1, Some(2))).is_empty());
+        assert_eq!(passed(&mut filter, frame(3, 1, Some(3))), vec![(3, 1)]);
+    }
+}
@@ -0,0 +1,380 @@
+use crate::cache::Cache;
+use crate::library::{self, AnalyzedGame};
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+pub const DEFAULT_PORT: u16 = 8080;
+
+/// Uploads bigger than this are turned away. Even long replays are only a few megabytes.
+const MAX_UPLOAD_BYTES: usize = 64 * 1024 * 1024;
+
+/// Request and header lines longer than this are turned away, instead of being read into memory
+/// for as long as the client keeps sending.
+const MAX_LINE_BYTES: usize = 8 * 1024;
+
+/// Serves analysis results over HTTP on localhost, one thread per connection.
+///
+/// - `POST /analyze` with a replay as the body (raw, or as the first file of a multipart form)
+///   analyzes it and returns the result.
+/// - `GET /replays` lists the replays under `dir`.
+/// - `GET /replays/<path>` returns the result for one of them, using the analysis cache.
+///
+/// Results are JSON: `{"game": AnalyzedGame, "stats": [ConversionStats for each player]}`.
+pub fn serve(dir: &Path, port: u16) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+    println!("Serving {} on http://127.0.0.1:{}", dir.display(), port);
+
+    let dir = Arc::new(dir.to_path_buf());
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Couldn't accept connection: {}", e);
+                continue;
+            }
+        };
+        let dir = Arc::clone(&dir);
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(stream, &dir) {
+                eprintln!("Request failed: {}", e);
+            }
+        });
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    content_type: Option<String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    body: Value,
+}
+
+impl Response {
+    fn ok(body: Value) -> Response {
+        Response { status: 200, body }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response {
+            status,
+            body: json!({ "error": message }),
+        }
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, dir: &Path) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = match read_request(&mut reader, &mut stream) {
+        Ok(request) => route(&request, dir),
+        Err(e) => Response::error(400, &e.to_string()),
+    };
+    write_response(stream, &response)
+}
+
+fn route(request: &Request, dir: &Path) -> Response {
+    let path = percent_decode(request.path.split('?').next().unwrap_or_default());
+    match (request.method.as_str(), path.as_str()) {
+        ("POST", "/analyze") => analyze_upload(request),
+        ("GET", "/replays") => {
+            let replays: Vec<String> = library::find_replays(dir)
+                .iter()
+                .filter_map(|p| p.strip_prefix(dir).ok())
+                .map(|p| p.display().to_string())
+                .collect();
+            Response::ok(json!(replays))
+        }
+        ("GET", path) if path.starts_with("/replays/") => match replay_path(dir, &path["/replays/".len()..]) {
+            Some(replay) => match Cache::open_default().analyze(&replay) {
+                Ok(game) => Response::ok(game_json(&game)),
+                Err(e) => Response::error(422, &e.to_string()),
+            },
+            None => Response::error(404, "No such replay"),
+        },
+        (_, "/analyze") | (_, "/replays") => Response::error(405, "Method not allowed"),
+        _ => Response::error(404, "Not found"),
+    }
+}
+
+fn analyze_upload(request: &Request) -> Response {
+    let replay = match &request.content_type {
+        Some(t) if t.starts_with("multipart/form-data") => match multipart_file(t, &request.body) {
+            Some(replay) => replay,
+            None => return Response::error(400, "No file in form"),
+        },
+        _ => &request.body[..],
+    };
+    match library::analyze_replay_from(Path::new("upload.slp"), &mut &replay[..]) {
+        Ok(game) => Response::ok(game_json(&game)),
+        Err(e) => Response::error(422, &e.to_string()),
+    }
+}
+
+fn game_json(game: &AnalyzedGame) -> Value {
+    json!({ "game": game, "stats": game.player_stats() })
+}
+
+/// The replay under `dir` at `relative`, as long as it exists and doesn't escape `dir`.
+fn replay_path(dir: &Path, relative: &str) -> Option<PathBuf> {
+    let relative = Path::new(relative);
+    if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    let path = dir.join(relative);
+    match path.is_file() && path.extension().is_some_and(|ext| ext == "slp") {
+        true => Some(path),
+        _ => None,
+    }
+}
+
+/// `writer` is only used to tell clients that wait for permission before uploading (curl does
+/// for big files) to go ahead.
+fn read_request<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) -> io::Result<Request> {
+    let mut line = String::new();
+    read_line(reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Bad request line")),
+    };
+
+    let mut content_length = 0;
+    let mut content_type = None;
+    let mut expect_continue = false;
+    loop {
+        line.clear();
+        if read_line(reader, &mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => {
+                    content_length = value
+                        .trim()
+                        .parse()
+                        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Bad Content-Length"))?
+                }
+                "content-type" => content_type = Some(value.trim().to_string()),
+                "expect" => expect_continue = value.trim().eq_ignore_ascii_case("100-continue"),
+                _ => (),
+            }
+        }
+    }
+    if content_length > MAX_UPLOAD_BYTES {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Upload too large"));
+    }
+    if expect_continue {
+        writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Request {
+        method,
+        path,
+        content_type,
+        body,
+    })
+}
+
+/// Like `BufRead::read_line`, but fails on lines longer than `MAX_LINE_BYTES`.
+fn read_line<R: BufRead>(reader: &mut R, line: &mut String) -> io::Result<usize> {
+    let read = reader.by_ref().take(MAX_LINE_BYTES as u64).read_line(line)?;
+    if read == MAX_LINE_BYTES && !line.ends_with('\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Line too long"));
+    }
+    Ok(read)
+}
+
+/// The reason phrase for the statuses we send. It's optional, so anything else gets none.
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        422 => "Unprocessable Entity",
+        500 => "Internal Server Error",
+        _ => "",
+    }
+}
+
+fn write_response(mut stream: TcpStream, response: &Response) -> io::Result<()> {
+    let body = response.body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason(response.status),
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// The contents of the first part of a `multipart/form-data` body that has a filename.
+fn multipart_file<'a>(content_type: &str, body: &'a [u8]) -> Option<&'a [u8]> {
+    let boundary = content_type.split(';').find_map(|p| p.trim().strip_prefix("boundary="))?;
+    let delimiter = format!("\r\n--{}", boundary.trim_matches('"'));
+    // The first boundary isn't preceded by a line break, so pretend it is.
+    let body_start = find(body, &delimiter.as_bytes()[2..])? + delimiter.len() - 2;
+    let mut rest = &body[body_start..];
+    loop {
+        let headers_end = find(rest, b"\r\n\r\n")?;
+        let headers = String::from_utf8_lossy(&rest[..headers_end]);
+        let content = &rest[headers_end + 4..];
+        let content_end = find(content, delimiter.as_bytes())?;
+        if headers.contains("filename=") {
+            return Some(&content[..content_end]);
+        }
+        rest = &content[content_end + delimiter.len()..];
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Decodes `%XX` escapes in a URL path, e.g. `Game%2020220101.slp`.
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(std::str::from_utf8(h).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(percent_decode("/replays/Game%2020220101.slp"), "/replays/Game 20220101.slp");
+        assert_eq!(percent_decode("/replays/%2e%2E/secret.slp"), "/replays/../secret.slp");
+        // Anything that isn't a whole escape is left alone.
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%2"), "%2");
+        assert_eq!(percent_decode("%zz%41"), "%zzA");
+    }
+
+    #[test]
+    fn replay_paths_stay_inside_the_directory() {
+        let dir = env::temp_dir().join(format!("slipnsights-server-{}", process::id()));
+        fs::create_dir_all(dir.join("2023-01")).unwrap();
+        fs::write(dir.join("game.slp"), b"").unwrap();
+        fs::write(dir.join("2023-01/game.slp"), b"").unwrap();
+        fs::write(dir.join("notes.txt"), b"").unwrap();
+
+        assert_eq!(replay_path(&dir, "game.slp"), Some(dir.join("game.slp")));
+        assert_eq!(replay_path(&dir, "2023-01/game.slp"), Some(dir.join("2023-01/game.slp")));
+        assert_eq!(replay_path(&dir, "../game.slp"), None);
+        assert_eq!(replay_path(&dir, "2023-01/../game.slp"), None);
+        assert_eq!(replay_path(&dir, "./game.slp"), None);
+        assert_eq!(replay_path(&dir, "/etc/passwd"), None);
+        assert_eq!(replay_path(&dir, "missing.slp"), None);
+        assert_eq!(replay_path(&dir, "notes.txt"), None);
+
+        // Escaped dots are decoded before the path is checked.
+        let request = Request {
+            method: "GET".to_string(),
+            path: "/replays/2023-01/%2e%2e/%2E%2E/game.slp".to_string(),
+            content_type: None,
+            body: Vec::new(),
+        };
+        assert_eq!(route(&request, &dir.join("2023-01")).status, 404);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn finds_the_file_in_a_multipart_form() {
+        let body = b"--XyZ\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nhello\r\n\
+            --XyZ\r\nContent-Disposition: form-data; name=\"replay\"; filename=\"game.slp\"\r\n\
+            Content-Type: application/octet-stream\r\n\r\n{U\x03raw\r\n--XyZ--\r\n";
+        assert_eq!(multipart_file("multipart/form-data; boundary=XyZ", body), Some(&b"{U\x03raw"[..]));
+        assert_eq!(multipart_file("multipart/form-data; boundary=\"XyZ\"", body), Some(&b"{U\x03raw"[..]));
+        assert_eq!(multipart_file("multipart/form-data", body), None);
+        assert_eq!(multipart_file("multipart/form-data; boundary=other", body), None);
+
+        let no_file = b"--XyZ\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nhello\r\n--XyZ--\r\n";
+        assert_eq!(multipart_file("multipart/form-data; boundary=XyZ", no_file), None);
+    }
+
+    #[test]
+    fn reads_requests() {
+        let raw = b"POST /analyze HTTP/1.1\r\nContent-Type: application/octet-stream\r\n\
+            content-length: 5\r\nExpect: 100-continue\r\n\r\nhello";
+        let mut written = Vec::new();
+        let request = read_request(&mut &raw[..], &mut written).unwrap();
+        assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/analyze"));
+        assert_eq!(request.content_type.as_deref(), Some("application/octet-stream"));
+        assert_eq!(request.body, b"hello");
+        assert_eq!(written, b"HTTP/1.1 100 Continue\r\n\r\n");
+
+        let mut sink = Vec::new();
+        assert!(read_request(&mut &b"\r\n\r\n"[..], &mut sink).is_err());
+        assert!(read_request(&mut &b"POST /analyze HTTP/1.1\r\nContent-Length: lots\r\n\r\n"[..], &mut sink).is_err());
+        let too_big = format!("POST /analyze HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_UPLOAD_BYTES + 1);
+        assert!(read_request(&mut too_big.as_bytes(), &mut sink).is_err());
+        // The body is shorter than it said it would be.
+        assert!(read_request(&mut &b"POST /analyze HTTP/1.1\r\nContent-Length: 10\r\n\r\nhi"[..], &mut sink).is_err());
+    }
+
+    #[test]
+    fn turns_away_long_lines() {
+        let mut sink = Vec::new();
+        let header = |len: usize| format!("GET /replays HTTP/1.1\r\nX-Padding: {}\r\n\r\n", "a".repeat(len));
+        // Just fits, counting the name and line break.
+        let longest = MAX_LINE_BYTES - "X-Padding: \r\n".len();
+        assert!(read_request(&mut header(longest).as_bytes(), &mut sink).is_ok());
+        assert!(read_request(&mut header(longest + 1).as_bytes(), &mut sink).is_err());
+
+        let path = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE_BYTES));
+        assert!(read_request(&mut path.as_bytes(), &mut sink).is_err());
+    }
+
+    #[test]
+    fn every_status_has_its_own_reason() {
+        assert_eq!(reason(404), "Not Found");
+        assert_eq!(reason(422), "Unprocessable Entity");
+        assert_eq!(reason(500), "Internal Server Error");
+        assert_eq!(reason(418), "");
+    }
+
+    #[test]
+    fn routes_by_method() {
+        let request = |method: &str, path: &str| Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            content_type: None,
+            body: Vec::new(),
+        };
+        let dir = Path::new("/nonexistent");
+        assert_eq!(route(&request("GET", "/analyze"), dir).status, 405);
+        assert_eq!(route(&request("DELETE", "/replays"), dir).status, 405);
+        assert_eq!(route(&request("GET", "/elsewhere"), dir).status, 404);
+        assert_eq!(route(&request("GET", "/replays?sort=name"), dir).status, 200);
+        assert_eq!(route(&request("POST", "/analyze"), dir).status, 422);
+    }
+}
@@ -0,0 +1,191 @@
+use crate::frameinfo::get_stage_string;
+use crate::library::AnalyzedGame;
+use crate::stats::ConversionStats;
+use chrono::Duration;
+use core::fmt::{self, Display};
+
+/// Games further apart than this (from the end of one to the start of the next) start a new set.
+const MAX_MINUTES_BETWEEN_GAMES: i64 = 15;
+
+/// Back-to-back games between the same players.
+pub struct Set {
+    /// Player names (see `PlayerInfo::name`), sorted.
+    pub players: Vec<String>,
+    /// In the order they were played.
+    pub games: Vec<AnalyzedGame>,
+}
+
+/// Groups games into sets: consecutive games with the same players, without a long break in between.
+/// Only games next to each other once sorted by start time can be in the same set, so a game between
+/// anyone else in the middle (say, on a shared setup) ends the set, and a new one starts after it.
+pub fn group_sets(mut games: Vec<AnalyzedGame>) -> Vec<Set> {
+    games.sort_by(|a, b| (a.info.date, &a.path).cmp(&(b.info.date, &b.path)));
+
+    let mut sets: Vec<Set> = Vec::new();
+    for game in games {
+        let players = player_names(&game);
+        match sets.last_mut() {
+            Some(set) if set.players == players && set.continues_with(&game) => set.games.push(game),
+            _ => sets.push(Set {
+                players,
+                games: vec![game],
+            }),
+        }
+    }
+    sets
+}
+
+fn player_names(game: &AnalyzedGame) -> Vec<String> {
+    let mut names: Vec<String> = game.info.players.iter().map(|p| p.name()).collect();
+    names.sort();
+    names
+}
+
+impl Set {
+    /// Replays without a start time can't be placed in time, so they only go by who played.
+    fn continues_with(&self, game: &AnalyzedGame) -> bool {
+        let last = self.games.last().unwrap();
+        match (last.info.end_time(), game.info.date) {
+            (Some(end), Some(start)) => start - end <= Duration::minutes(MAX_MINUTES_BETWEEN_GAMES),
+            _ => true,
+        }
+    }
+
+    /// Conversion stats for each player across the whole set, in the same order as `players`.
+    pub fn player_stats(&self) -> Vec<ConversionStats> {
+        let mut totals = vec![ConversionStats::default(); self.players.len()];
+        for game in &self.games {
+            for (player, stats) in game.info.players.iter().zip(game.player_stats()) {
+                if let Some(i) = self.players.iter().position(|p| *p == player.name()) {
+                    totals[i].add(&stats);
+                }
+            }
+        }
+        totals
+    }
+
+    /// The player with the most game wins, if there is exactly one.
+    pub fn winner(&self) -> Option<&String> {
+        let wins: Vec<usize> = self.player_stats().iter().map(|s| s.wins).collect();
+        let most = *wins.iter().max()?;
+        match wins.iter().filter(|w| **w == most).count() {
+            1 => self.players.get(wins.iter().position(|w| *w == most)?),
+            _ => None,
+        }
+    }
+
+    /// Game wins for each player, e.g. `3 - 1`.
+    pub fn score(&self) -> String {
+        self.player_stats()
+            .iter()
+            .map(|s| s.wins.to_string())
+            .collect::<Vec<String>>()
+            .join(" - ")
+    }
+}
+
+impl Display for Set {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.players.join(" vs "), self.score())?;
+        if let Some(winner) = self.winner() {
+            write!(f, ", {} wins", winner)?;
+        }
+
+        for game in &self.games {
+            let date = match game.info.date {
+                Some(date) => date.format("%Y-%m-%d %H:%M").to_string(),
+                None => "Unknown date".to_string(),
+            };
+            let winners = game
+                .info
+                .winners
+                .iter()
+                .filter_map(|port| game.info.player(*port))
+                .map(|p| p.name())
+                .collect::<Vec<String>>()
+                .join(", ");
+            write!(
+                f,
+                "\n   {}  {} on {}, won by {}  ({})",
+                date,
+                game.info.matchup(),
+                get_stage_string(game.info.stage),
+                if winners.is_empty() { "nobody".to_string() } else { winners },
+                game.path.display()
+            )?;
+        }
+
+        for (player, stats) in self.players.iter().zip(self.player_stats()) {
+            write!(f, "\n   {}: {}", player, stats)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, player};
+    use chrono::{TimeZone, Utc};
+    use peppi::model::enums::character::External;
+    use peppi::model::primitives::Port;
+
+    /// A minute long game between two connect codes, starting `minute` minutes in, won by `winner`.
+    fn game(codes: [&str; 2], minute: i64, winner: usize) -> AnalyzedGame {
+        let players = vec![
+            player(Port::P1, External::FOX, Some(codes[0])),
+            player(Port::P2, External::MARTH, Some(codes[1])),
+        ];
+        let mut game = testing::game(players, Vec::new());
+        game.info.date = Some(Utc.ymd(2023, 1, 1).and_hms(12, 0, 0) + Duration::minutes(minute));
+        game.info.winners = vec![[Port::P1, Port::P2][winner]];
+        game.path = format!("{}.slp", minute).into();
+        game
+    }
+
+    fn lengths(sets: &[Set]) -> Vec<usize> {
+        sets.iter().map(|s| s.games.len()).collect()
+    }
+
+    #[test]
+    fn groups_back_to_back_games() {
+        let sets = group_sets(vec![
+            game(["AAAA#1", "BBBB#2"], 10, 0),
+            game(["AAAA#1", "BBBB#2"], 0, 1),
+            // Players on the other ports are still the same players.
+            game(["BBBB#2", "AAAA#1"], 5, 1),
+        ]);
+        assert_eq!(lengths(&sets), [3]);
+        assert_eq!(sets[0].players, ["AAAA#1", "BBBB#2"]);
+        let paths: Vec<_> = sets[0].games.iter().map(|g| g.path.display().to_string()).collect();
+        assert_eq!(paths, ["0.slp", "5.slp", "10.slp"]);
+    }
+
+    #[test]
+    fn long_breaks_and_other_players_start_new_sets() {
+        let sets = group_sets(vec![
+            game(["AAAA#1", "BBBB#2"], 0, 0),
+            // Starts more than 15 minutes after the last one ended.
+            game(["AAAA#1", "BBBB#2"], 20, 0),
+            game(["AAAA#1", "CCCC#3"], 22, 0),
+            // Same players as before, but not right after their last game.
+            game(["AAAA#1", "BBBB#2"], 24, 0),
+        ]);
+        assert_eq!(lengths(&sets), [1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn counts_game_wins() {
+        let sets = group_sets(vec![
+            game(["AAAA#1", "BBBB#2"], 0, 0),
+            game(["AAAA#1", "BBBB#2"], 2, 1),
+            game(["BBBB#2", "AAAA#1"], 4, 1),
+        ]);
+        assert_eq!(sets[0].score(), "2 - 1");
+        assert_eq!(sets[0].winner().map(String::as_str), Some("AAAA#1"));
+
+        let tied = group_sets(vec![game(["AAAA#1", "BBBB#2"], 0, 0), game(["AAAA#1", "BBBB#2"], 2, 1)]);
+        assert_eq!(tied[0].score(), "1 - 1");
+        assert_eq!(tied[0].winner(), None);
+    }
+}
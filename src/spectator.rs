@@ -0,0 +1,666 @@
+use crate::events::PrintSink;
+use crate::stream::LiveAnalysis;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+use std::{process, thread};
+
+/// Port that consoles and the Slippi relay listen on for spectators.
+pub const DEFAULT_PORT: u16 = 51441;
+
+const HANDSHAKE: u8 = 1;
+const REPLAY: u8 = 2;
+const KEEP_ALIVE: u8 = 3;
+
+const EVENT_PAYLOADS: u8 = 0x35;
+const GAME_END: u8 = 0x39;
+
+/// Messages longer than this are treated as corrupt instead of being read into memory.
+const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+// What a replay file looks like up to the first event, with a raw length of 0 like an in-progress replay.
+const RAW_HEADER: [u8; 15] = [
+    0x7b, 0x55, 0x03, 0x72, 0x61, 0x77, 0x5b, 0x24, 0x55, 0x23, 0x6c, 0, 0, 0, 0,
+];
+// Empty metadata and the closing brace, which peppi expects right after the game end event.
+const METADATA_TRAILER: [u8; 13] = [
+    0x55, 0x08, 0x6d, 0x65, 0x74, 0x61, 0x64, 0x61, 0x74, 0x61, 0x7b, 0x7d, 0x7d,
+];
+
+/// Connects to a console (or Slippi relay) at `address` and prints conversions for each game as it is played.
+///
+/// This speaks the TCP protocol used by slippi-js's `ConsoleConnection`. Dolphin's ENet spectator
+/// protocol isn't supported, but Dolphin already writes replays to disk, so use `watch` instead.
+pub fn spectate(address: &str) {
+    let address = match address.contains(':') {
+        true => address.to_string(),
+        _ => format!("{}:{}", address, DEFAULT_PORT),
+    };
+    let stream = match TcpStream::connect(&address) {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!("Couldn't connect to {}: {}", address, e);
+            process::exit(1);
+        }
+    };
+    println!("Connected to {}", address);
+
+    let (tx, rx) = mpsc::channel();
+    let connection = thread::spawn(move || {
+        if let Err(e) = receive(stream, tx) {
+            eprintln!("Connection closed: {}", e);
+        }
+    });
+
+    let mut events = EventStream::new(rx);
+    while events.wait_for_game() {
+        let init_time = Instant::now();
+        let mut analysis = LiveAnalysis::new(PrintSink::default());
+        let result = peppi::parse(&mut GameReader::new(&mut events), &mut analysis, None);
+        match result {
+            Err(e) if !analysis.game_ended() => eprintln!("Stopped following game: {}", e),
+            _ => println!("{:#?}", init_time.elapsed()),
+        }
+        if analysis.rollback_stats().rollbacks > 0 {
+            println!("{}", analysis.rollback_stats());
+        }
+    }
+
+    connection.join().unwrap();
+}
+
+/// Handshakes with the console, then forwards the replay data from every message to `tx`.
+fn receive(mut stream: TcpStream, tx: Sender<Vec<u8>>) -> io::Result<()> {
+    write_message(&mut stream, &handshake(&[0; 8], &[0; 4]))?;
+
+    loop {
+        let message = read_message(&mut stream)?;
+        match message.get("type").and_then(Value::as_int) {
+            Some(t) if t == HANDSHAKE as i64 => {
+                let payload = message.get("payload");
+                let nick = payload.and_then(|p| p.get("nick")).and_then(Value::as_str);
+                let version = payload.and_then(|p| p.get("nintendontVersion")).and_then(Value::as_str);
+                println!("Handshake: {} (Nintendont {})", nick.unwrap_or("Unknown"), version.unwrap_or("Unknown"));
+            }
+            Some(t) if t == REPLAY as i64 => {
+                let data = message.get("payload").and_then(|p| p.get("data"));
+                if let Some(Value::Bytes(data)) = data {
+                    if tx.send(data.clone()).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+            Some(t) if t == KEEP_ALIVE as i64 => (),
+            t => eprintln!("Ignoring unknown message type: {:?}", t),
+        }
+    }
+}
+
+/// Splits the raw bytes coming from the console back into individual Slippi events.
+struct EventStream {
+    rx: Receiver<Vec<u8>>,
+    buf: VecDeque<u8>,
+    payload_sizes: HashMap<u8, u16>,
+}
+
+impl EventStream {
+    fn new(rx: Receiver<Vec<u8>>) -> EventStream {
+        EventStream {
+            rx,
+            buf: VecDeque::new(),
+            payload_sizes: HashMap::new(),
+        }
+    }
+
+    /// Blocks until `buf` holds at least `len` bytes. Returns false once the connection is gone.
+    fn fill(&mut self, len: usize) -> bool {
+        while self.buf.len() < len {
+            match self.rx.recv() {
+                Ok(data) => self.buf.extend(data),
+                Err(_) => return false,
+            }
+        }
+        true
+    }
+
+    /// Skips ahead to the start of the next game. Returns false once the connection is gone.
+    fn wait_for_game(&mut self) -> bool {
+        loop {
+            if !self.fill(1) {
+                return false;
+            }
+            if self.buf[0] == EVENT_PAYLOADS {
+                return true;
+            }
+            // Without the payload sizes from the start of a game we can't tell where events end.
+            if self.next_event().is_none() {
+                self.buf.clear();
+            }
+        }
+    }
+
+    /// The next whole event, including its command byte.
+    fn next_event(&mut self) -> Option<Vec<u8>> {
+        if !self.fill(2) {
+            return None;
+        }
+        let code = self.buf[0];
+        let size = match code {
+            EVENT_PAYLOADS => self.buf[1] as usize,
+            _ => *self.payload_sizes.get(&code)? as usize,
+        };
+        if !self.fill(1 + size) {
+            return None;
+        }
+        let event: Vec<u8> = self.buf.drain(..1 + size).collect();
+
+        if code == EVENT_PAYLOADS {
+            self.payload_sizes = event[2..]
+                .chunks_exact(3)
+                .map(|c| (c[0], u16::from_be_bytes([c[1], c[2]])))
+                .collect();
+        }
+        Some(event)
+    }
+}
+
+/// Presents a single game from an `EventStream` as if it were a replay file being written.
+struct GameReader<'a> {
+    events: &'a mut EventStream,
+    out: VecDeque<u8>,
+    started: bool,
+    ended: bool,
+}
+
+impl<'a> GameReader<'a> {
+    fn new(events: &'a mut EventStream) -> GameReader<'a> {
+        GameReader {
+            events,
+            out: VecDeque::new(),
+            started: false,
+            ended: false,
+        }
+    }
+}
+
+impl Read for GameReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.out.is_empty() && !self.ended {
+            if !self.started {
+                self.out.extend(RAW_HEADER);
+                self.started = true;
+            }
+            match self.events.next_event() {
+                Some(event) => {
+                    self.ended = event[0] == GAME_END;
+                    self.out.extend(event);
+                    if self.ended {
+                        self.out.extend(METADATA_TRAILER);
+                    }
+                }
+                None => self.ended = true,
+            }
+        }
+        self.out.read(buf)
+    }
+}
+
+/// Pretends to be a console: waits for a spectator on `port`, then streams `path` to it a chunk at a time.
+pub fn serve_replay(path: &Path, port: u16) {
+    let raw = match fs::read(path).and_then(|replay| raw_events(&replay).map(<[u8]>::to_vec)) {
+        Ok(raw) => raw,
+        Err(e) => {
+            eprintln!("Can't read {}: {}", path.display(), e);
+            process::exit(1);
+        }
+    };
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+    println!("Serving {} on port {}", path.display(), port);
+
+    for stream in listener.incoming() {
+        let mut stream = stream.unwrap();
+        if let Err(e) = stream_replay(&mut stream, &raw) {
+            eprintln!("Spectator disconnected: {}", e);
+        }
+    }
+}
+
+fn stream_replay(stream: &mut TcpStream, raw: &[u8]) -> io::Result<()> {
+    read_message(stream)?;
+    write_message(stream, &console_handshake())?;
+
+    let mut pos: u64 = 0;
+    for chunk in raw.chunks(1024) {
+        let next_pos = pos + chunk.len() as u64;
+        write_message(stream, &replay(pos, next_pos, chunk))?;
+        pos = next_pos;
+        thread::sleep(Duration::from_millis(2));
+    }
+    write_message(stream, &keep_alive())
+}
+
+/// The contents of a replay's `raw` element, which is exactly what a console sends.
+fn raw_events(replay: &[u8]) -> io::Result<&[u8]> {
+    // Everything up to the length, which is the same in every replay.
+    if replay.len() < RAW_HEADER.len() || replay[..11] != RAW_HEADER[..11] {
+        return Err(invalid("not a Slippi replay".to_string()));
+    }
+    let raw_len = u32::from_be_bytes([replay[11], replay[12], replay[13], replay[14]]) as usize;
+    let end = match raw_len {
+        // In-progress replays don't have a length yet, so look for where the metadata starts instead.
+        0 => replay
+            .windows(11)
+            .position(|w| w == &METADATA_TRAILER[..11])
+            .unwrap_or(replay.len()),
+        _ if RAW_HEADER.len() + raw_len <= replay.len() => RAW_HEADER.len() + raw_len,
+        _ => return Err(invalid(format!("replay is cut off, expected {} bytes of events", raw_len))),
+    };
+    Ok(&replay[RAW_HEADER.len()..end])
+}
+
+fn handshake(cursor: &[u8], client_token: &[u8]) -> Vec<u8> {
+    message(
+        HANDSHAKE,
+        &[
+            ("cursor", Value::Bytes(cursor.to_vec())),
+            ("clientToken", Value::Bytes(client_token.to_vec())),
+            ("isRealtime", Value::Bool(false)),
+        ],
+    )
+}
+
+fn console_handshake() -> Vec<u8> {
+    message(
+        HANDSHAKE,
+        &[
+            ("nick", Value::String("slipnsights".to_string())),
+            ("nintendontVersion", Value::String("1.11.1".to_string())),
+            ("clientToken", Value::Bytes(vec![0; 4])),
+            ("pos", Value::Bytes(vec![0; 8])),
+        ],
+    )
+}
+
+fn replay(pos: u64, next_pos: u64, data: &[u8]) -> Vec<u8> {
+    message(
+        REPLAY,
+        &[
+            ("pos", Value::Bytes(pos.to_be_bytes().to_vec())),
+            ("nextPos", Value::Bytes(next_pos.to_be_bytes().to_vec())),
+            ("data", Value::Bytes(data.to_vec())),
+        ],
+    )
+}
+
+fn keep_alive() -> Vec<u8> {
+    message(KEEP_ALIVE, &[])
+}
+
+fn message(kind: u8, payload: &[(&str, Value)]) -> Vec<u8> {
+    let mut buf = vec![b'{'];
+    write_key(&mut buf, "type");
+    write_value(&mut buf, &Value::Int(kind as i64));
+    write_key(&mut buf, "payload");
+    buf.push(b'{');
+    for (key, value) in payload {
+        write_key(&mut buf, key);
+        write_value(&mut buf, value);
+    }
+    buf.extend(b"}}");
+    buf
+}
+
+fn write_message(stream: &mut impl Write, message: &[u8]) -> io::Result<()> {
+    stream.write_all(&(message.len() as u32).to_be_bytes())?;
+    stream.write_all(message)
+}
+
+fn read_message(stream: &mut impl Read) -> io::Result<Value> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MESSAGE_LEN {
+        return Err(invalid(format!("message too long: {} bytes", len)));
+    }
+    let mut message = vec![0; len];
+    stream.read_exact(&mut message)?;
+    read_value(&mut &message[..])
+}
+
+/// The subset of UBJSON the console protocol uses.
+#[derive(Debug, PartialEq)]
+enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_key(buf: &mut Vec<u8>, key: &str) {
+    write_int(buf, key.len() as i64);
+    buf.extend(key.as_bytes());
+}
+
+fn write_int(buf: &mut Vec<u8>, i: i64) {
+    if let Ok(i) = u8::try_from(i) {
+        buf.extend([b'U', i]);
+    } else if let Ok(i) = i32::try_from(i) {
+        buf.push(b'l');
+        buf.extend(i.to_be_bytes());
+    } else {
+        buf.push(b'L');
+        buf.extend(i.to_be_bytes());
+    }
+}
+
+fn write_value(buf: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => buf.push(b'Z'),
+        Value::Bool(b) => buf.push(if *b { b'T' } else { b'F' }),
+        Value::Int(i) => write_int(buf, *i),
+        Value::Float(f) => {
+            buf.push(b'D');
+            buf.extend(f.to_be_bytes());
+        }
+        Value::String(s) => {
+            buf.push(b'S');
+            write_key(buf, s);
+        }
+        Value::Bytes(bytes) => {
+            buf.extend(b"[$U#");
+            write_int(buf, bytes.len() as i64);
+            buf.extend(bytes);
+        }
+        Value::Array(values) => {
+            buf.push(b'[');
+            for v in values {
+                write_value(buf, v);
+            }
+            buf.push(b']');
+        }
+        Value::Object(fields) => {
+            buf.push(b'{');
+            for (k, v) in fields {
+                write_key(buf, k);
+                write_value(buf, v);
+            }
+            buf.push(b'}');
+        }
+    }
+}
+
+fn read_bytes<const N: usize>(r: &mut &[u8]) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    r.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_int(r: &mut &[u8], marker: u8) -> io::Result<i64> {
+    Ok(match marker {
+        b'i' => i8::from_be_bytes(read_bytes(r)?) as i64,
+        b'U' => u8::from_be_bytes(read_bytes(r)?) as i64,
+        b'I' => i16::from_be_bytes(read_bytes(r)?) as i64,
+        b'l' => i32::from_be_bytes(read_bytes(r)?) as i64,
+        b'L' => i64::from_be_bytes(read_bytes(r)?),
+        m => return Err(invalid(format!("expected int, but got marker: {:#x}", m))),
+    })
+}
+
+fn read_len(r: &mut &[u8]) -> io::Result<usize> {
+    let [marker] = read_bytes(r)?;
+    let len = read_int(r, marker)?;
+    usize::try_from(len).map_err(|_| invalid(format!("invalid length: {}", len)))
+}
+
+/// The next `len` bytes, checking they're there before copying anything.
+fn read_slice(r: &mut &[u8], len: usize) -> io::Result<Vec<u8>> {
+    if len > r.len() {
+        return Err(invalid(format!("length {} is past the end of the message", len)));
+    }
+    let (bytes, rest) = r.split_at(len);
+    *r = rest;
+    Ok(bytes.to_vec())
+}
+
+fn read_string(r: &mut &[u8]) -> io::Result<String> {
+    let len = read_len(r)?;
+    String::from_utf8(read_slice(r, len)?).map_err(|e| invalid(format!("invalid string: {}", e)))
+}
+
+fn read_value(r: &mut &[u8]) -> io::Result<Value> {
+    let [marker] = read_bytes(r)?;
+    read_value_of(r, marker)
+}
+
+fn read_value_of(r: &mut &[u8], marker: u8) -> io::Result<Value> {
+    Ok(match marker {
+        b'Z' => Value::Null,
+        b'T' => Value::Bool(true),
+        b'F' => Value::Bool(false),
+        b'i' | b'U' | b'I' | b'l' | b'L' => Value::Int(read_int(r, marker)?),
+        b'd' => Value::Float(f32::from_be_bytes(read_bytes(r)?) as f64),
+        b'D' => Value::Float(f64::from_be_bytes(read_bytes(r)?)),
+        b'C' => Value::String((read_bytes::<1>(r)?[0] as char).to_string()),
+        b'S' | b'H' => Value::String(read_string(r)?),
+        b'[' => read_container(r, b']', false)?,
+        b'{' => read_container(r, b'}', true)?,
+        m => return Err(invalid(format!("unknown marker: {:#x}", m))),
+    })
+}
+
+/// Reads an array or object, after its opening marker.
+fn read_container(r: &mut &[u8], close: u8, is_object: bool) -> io::Result<Value> {
+    let mut kind = None;
+    let mut count = None;
+    if r.first() == Some(&b'$') {
+        *r = &r[1..];
+        kind = Some(read_bytes::<1>(r)?[0]);
+    }
+    if r.first() == Some(&b'#') {
+        *r = &r[1..];
+        count = Some(read_len(r)?);
+    }
+
+    if !is_object && kind == Some(b'U') {
+        if let Some(count) = count {
+            return Ok(Value::Bytes(read_slice(r, count)?));
+        }
+    }
+
+    let mut keys = Vec::new();
+    let mut values = Vec::new();
+    loop {
+        match count {
+            Some(count) if values.len() == count => break,
+            None if r.first() == Some(&close) => {
+                *r = &r[1..];
+                break;
+            }
+            _ => (),
+        }
+        if is_object {
+            keys.push(read_string(r)?);
+        }
+        values.push(match kind {
+            Some(marker) => read_value_of(r, marker)?,
+            None => read_value(r)?,
+        });
+    }
+
+    Ok(match is_object {
+        true => Value::Object(keys.into_iter().zip(values).collect()),
+        _ => Value::Array(values),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::env;
+
+    /// A finished replay with `frames` made-up frame events, plus the events on their own.
+    fn replay_with_events(frames: usize) -> (Vec<u8>, Vec<u8>) {
+        let mut raw = vec![EVENT_PAYLOADS, 7, 0x38, 0, 4, GAME_END, 0, 1];
+        for frame in 0..frames {
+            raw.push(0x38);
+            raw.extend((frame as u32).to_be_bytes());
+        }
+        raw.extend([GAME_END, 2]);
+
+        let mut replay = RAW_HEADER[..11].to_vec();
+        replay.extend((raw.len() as u32).to_be_bytes());
+        replay.extend(&raw);
+        replay.extend(METADATA_TRAILER);
+        (replay, raw)
+    }
+
+    #[test]
+    fn ubjson_round_trip() {
+        let value = Value::Object(vec![
+            ("null".to_string(), Value::Null),
+            ("yes".to_string(), Value::Bool(true)),
+            ("no".to_string(), Value::Bool(false)),
+            ("small".to_string(), Value::Int(200)),
+            ("negative".to_string(), Value::Int(-3)),
+            ("big".to_string(), Value::Int(1 << 40)),
+            ("float".to_string(), Value::Float(1.5)),
+            ("string".to_string(), Value::String("Nintendont".to_string())),
+            ("bytes".to_string(), Value::Bytes(vec![0, 1, 255])),
+            ("array".to_string(), Value::Array(vec![Value::Int(1), Value::String("a".to_string())])),
+            ("object".to_string(), Value::Object(vec![("inner".to_string(), Value::Bool(true))])),
+        ]);
+        let mut buf = Vec::new();
+        write_value(&mut buf, &value);
+        assert_eq!(read_value(&mut &buf[..]).unwrap(), value);
+    }
+
+    #[test]
+    fn message_round_trip() {
+        let mut buf = Vec::new();
+        write_message(&mut buf, &replay(0, 3, &[1, 2, 3])).unwrap();
+        let message = read_message(&mut &buf[..]).unwrap();
+        assert_eq!(message.get("type").and_then(Value::as_int), Some(REPLAY as i64));
+        assert_eq!(message.get("payload").and_then(|p| p.get("data")), Some(&Value::Bytes(vec![1, 2, 3])));
+    }
+
+    #[test]
+    fn rejects_lengths_past_the_end() {
+        // A byte array and a string claiming to be far longer than what follows.
+        assert!(read_value(&mut &b"[$U#l\x7f\xff\xff\xff\x01\x02"[..]).is_err());
+        assert!(read_value(&mut &b"SL\x00\x00\x00\x10\x00\x00\x00\x00abc"[..]).is_err());
+        assert!(read_value(&mut &b"SU\x05abc"[..]).is_err());
+        assert!(read_message(&mut &[0xff, 0xff, 0xff, 0xff][..]).is_err());
+    }
+
+    #[test]
+    fn raw_events_checks_the_header() {
+        let (replay, raw) = replay_with_events(3);
+        assert_eq!(raw_events(&replay).unwrap(), &raw[..]);
+        assert!(raw_events(&replay[..10]).is_err());
+        assert!(raw_events(b"definitely not a replay").is_err());
+        // The length says there's more than there is.
+        assert!(raw_events(&replay[..replay.len() - METADATA_TRAILER.len() - 1]).is_err());
+
+        // In-progress replays have no length, so the events run up to the metadata.
+        let mut in_progress = replay.clone();
+        in_progress[11..15].copy_from_slice(&[0; 4]);
+        assert_eq!(raw_events(&in_progress).unwrap(), &raw[..]);
+    }
+
+    #[test]
+    fn mock_console_loopback() {
+        // Several chunks' worth of events.
+        let (replay, raw) = replay_with_events(600);
+        let path = env::temp_dir().join(format!("slipnsights-spectator-{}.slp", process::id()));
+        fs::write(&path, replay).unwrap();
+
+        let port = TcpListener::bind(("127.0.0.1", 0)).unwrap().local_addr().unwrap().port();
+        let server_path = path.clone();
+        thread::spawn(move || serve_replay(&server_path, port));
+        let started = Instant::now();
+        let stream = loop {
+            match TcpStream::connect(("127.0.0.1", port)) {
+                Ok(stream) => break stream,
+                Err(_) if started.elapsed() < Duration::from_secs(5) => thread::sleep(Duration::from_millis(10)),
+                Err(e) => panic!("mock console never started: {}", e),
+            }
+        };
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || receive(stream, tx));
+        let mut events = EventStream::new(rx);
+        assert!(events.wait_for_game());
+        let mut received = Vec::new();
+        while let Some(event) = events.next_event() {
+            let ended = event[0] == GAME_END;
+            received.extend(event);
+            if ended {
+                break;
+            }
+        }
+        fs::remove_file(&path).unwrap();
+        assert_eq!(received, raw);
+    }
+
+    #[test]
+    fn follows_each_game_on_a_connection() {
+        // Two games back to back, split up without regard for where events start.
+        let replay = testing::fair_replay(600);
+        let raw = raw_events(&replay).unwrap();
+        let (tx, rx) = mpsc::channel();
+        for chunk in [raw, raw].concat().chunks(1000) {
+            tx.send(chunk.to_vec()).unwrap();
+        }
+        drop(tx);
+
+        let mut events = EventStream::new(rx);
+        let mut games = Vec::new();
+        while events.wait_for_game() {
+            let mut analysis = LiveAnalysis::new(Vec::new());
+            peppi::parse(&mut GameReader::new(&mut events), &mut analysis, None).unwrap();
+            assert!(analysis.game_ended());
+            games.push(analysis.into_sink());
+        }
+        assert_eq!(games.len(), 2);
+        for conversions in games {
+            assert_eq!(conversions.len(), 1);
+            assert_eq!(conversions[0].attacks[0].name(), "FAIR");
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use peppi::model::enums::stage::Stage;
+use serde::{Deserialize, Serialize};
+
+/// How far in from the ledge still counts as being at the ledge.
+const LEDGE_DISTANCE: f32 = 30.0;
+/// Higher than this above the ledge, a player near it is in the air over the stage instead.
+const LEDGE_HEIGHT: f32 = 40.0;
+/// Below this height the main stage is out of reach without recovering, even inside the ledges.
+const BELOW_STAGE: f32 = -5.0;
+/// Anything from just under a platform to this far above it counts as being on it.
+const PLATFORM_MARGIN: f32 = 10.0;
+
+/// Rough areas of a stage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StageZone {
+    Center,
+    Ledge,
+    Offstage,
+    Platform,
+}
+
+impl StageZone {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StageZone::Center => "center",
+            StageZone::Ledge => "ledge",
+            StageZone::Offstage => "offstage",
+            StageZone::Platform => "platform",
+        }
+    }
+}
+
+/// A platform's top, in game units. Most platforms stay put, so `lowest` and `highest` are the same.
+#[derive(Clone, Copy, Debug)]
+pub struct Platform {
+    pub left: f32,
+    pub right: f32,
+    pub lowest: f32,
+    pub highest: f32,
+}
+
+impl Platform {
+    const fn fixed(left: f32, right: f32, height: f32) -> Platform {
+        Platform { left, right, lowest: height, highest: height }
+    }
+
+    const fn moving(left: f32, right: f32, lowest: f32, highest: f32) -> Platform {
+        Platform { left, right, lowest, highest }
+    }
+
+    fn is_on(&self, x: f32, y: f32) -> bool {
+        x >= self.left && x <= self.right && y >= self.lowest - 1.0 && y < self.highest + PLATFORM_MARGIN
+    }
+}
+
+/// Past any of these, a player loses a stock.
+#[derive(Clone, Copy, Debug)]
+pub struct BlastZones {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+}
+
+/// Another layout a stage switches to partway through a game. Each side's terrain is sketched as one
+/// flat platform at roughly its top, so its slopes, walls and any smaller ledges are left out.
+#[derive(Clone, Copy, Debug)]
+pub struct Transformation {
+    pub name: &'static str,
+    pub platforms: &'static [Platform],
+}
+
+/// Main stage, platform and blast zone positions. The main stage's top is at 0 and its ledges mirror
+/// each other, but platforms and blast zones are given side by side since they don't always (Dream
+/// Land's side platforms, Stadium's transformations and Yoshi's Story's blast zones).
+#[derive(Clone, Copy, Debug)]
+pub struct StageGeometry {
+    pub stage: Stage,
+    /// Distance from the center to either ledge. The ledges are at `(-ledge_x, 0)` and `(ledge_x, 0)`.
+    pub ledge_x: f32,
+    pub platforms: &'static [Platform],
+    pub blast_zones: BlastZones,
+    /// Pokémon Stadium's transformations replace its platforms with other terrain (the main stage stays).
+    /// Their positions are guesses rather than measurements (see `Transformation`), so they're drawn on
+    /// heatmaps but left out of zones, where standing on them counts as the center or the ledge.
+    pub transformations: &'static [Transformation],
+}
+
+impl StageGeometry {
+    /// The ledges' positions, left then right.
+    pub fn ledges(&self) -> [(f32, f32); 2] {
+        [(-self.ledge_x, 0.0), (self.ledge_x, 0.0)]
+    }
+}
+
+/// Tournament legal stages. Positions are rounded, Fountain of Dreams' platform range is only approximate,
+/// and Stadium's transformation terrain hasn't been measured.
+pub static LEGAL_STAGES: [StageGeometry; 6] = [
+    StageGeometry {
+        stage: Stage::FOUNTAIN_OF_DREAMS,
+        ledge_x: 63.35,
+        // The side platforms slowly rise and sink on their own, independently of each other.
+        platforms: &[
+            Platform::moving(-49.5, -21.0, 4.5, 28.13),
+            Platform::moving(21.0, 49.5, 4.5, 28.13),
+            Platform::fixed(-14.25, 14.25, 42.75),
+        ],
+        blast_zones: BlastZones { left: -198.75, right: 198.75, top: 202.5, bottom: -146.25 },
+        transformations: &[],
+    },
+    StageGeometry {
+        stage: Stage::POKEMON_STADIUM,
+        ledge_x: 87.75,
+        platforms: &[Platform::fixed(-55.0, -25.0, 25.0), Platform::fixed(25.0, 55.0, 25.0)],
+        blast_zones: BlastZones { left: -230.0, right: 230.0, top: 180.0, bottom: -111.0 },
+        transformations: &[
+            Transformation {
+                name: "fire",
+                platforms: &[Platform::fixed(-60.0, -40.0, 16.0), Platform::fixed(37.0, 60.0, 30.0)],
+            },
+            Transformation {
+                name: "grass",
+                platforms: &[Platform::fixed(-52.0, -30.0, 20.0), Platform::fixed(30.0, 52.0, 20.0)],
+            },
+            Transformation {
+                name: "rock",
+                platforms: &[Platform::fixed(-55.0, -30.0, 28.0), Platform::fixed(30.0, 50.0, 14.0)],
+            },
+            Transformation {
+                name: "water",
+                platforms: &[Platform::fixed(-62.0, -40.0, 26.0), Platform::fixed(30.0, 60.0, 18.0)],
+            },
+        ],
+    },
+    StageGeometry {
+        stage: Stage::YOSHIS_STORY,
+        ledge_x: 56.0,
+        platforms: &[
+            Platform::fixed(-59.5, -28.0, 23.45),
+            Platform::fixed(28.0, 59.5, 23.45),
+            Platform::fixed(-15.75, 15.75, 42.0),
+        ],
+        blast_zones: BlastZones { left: -175.7, right: 173.6, top: 168.0, bottom: -91.0 },
+        transformations: &[],
+    },
+    StageGeometry {
+        stage: Stage::DREAM_LAND_N64,
+        ledge_x: 77.27,
+        platforms: &[
+            Platform::fixed(-61.39, -31.73, 30.24),
+            Platform::fixed(31.70, 63.08, 30.24),
+            Platform::fixed(-19.02, 19.02, 51.43),
+        ],
+        blast_zones: BlastZones { left: -255.0, right: 255.0, top: 250.0, bottom: -123.0 },
+        transformations: &[],
+    },
+    StageGeometry {
+        stage: Stage::BATTLEFIELD,
+        ledge_x: 68.4,
+        platforms: &[
+            Platform::fixed(-57.6, -20.0, 27.2),
+            Platform::fixed(20.0, 57.6, 27.2),
+            Platform::fixed(-18.8, 18.8, 54.4),
+        ],
+        blast_zones: BlastZones { left: -224.0, right: 224.0, top: 200.0, bottom: -108.8 },
+        transformations: &[],
+    },
+    StageGeometry {
+        stage: Stage::FINAL_DESTINATION,
+        ledge_x: 85.5657,
+        platforms: &[],
+        blast_zones: BlastZones { left: -246.0, right: 246.0, top: 188.0, bottom: -140.0 },
+        transformations: &[],
+    },
+];
+
+/// Every stage's geometry and the zone thresholds, so analysis done with different ones can be told apart.
+pub fn settings() -> String {
+    format!(
+        "{:?} ledge={} ledge-height={} below={} platform={}",
+        LEGAL_STAGES, LEDGE_DISTANCE, LEDGE_HEIGHT, BELOW_STAGE, PLATFORM_MARGIN
+    )
+}
+
+/// Geometry for a tournament legal stage (by the id in `game.start`), or `None` for anything else.
+pub fn geometry(stage: Stage) -> Option<&'static StageGeometry> {
+    LEGAL_STAGES.iter().find(|g| g.stage == stage)
+}
+
+/// Which part of `stage` the point `(x, y)` is in, if the stage's geometry is known.
+pub fn zone(stage: Stage, x: f32, y: f32) -> Option<StageZone> {
+    let geometry = geometry(stage)?;
+    let zone = if x.abs() > geometry.ledge_x || y < BELOW_STAGE {
+        StageZone::Offstage
+    } else if geometry.platforms.iter().any(|p| p.is_on(x, y)) {
+        StageZone::Platform
+    } else if x.abs() > geometry.ledge_x - LEDGE_DISTANCE && y < LEDGE_HEIGHT {
+        StageZone::Ledge
+    } else {
+        StageZone::Center
+    };
+    Some(zone)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zones_on_battlefield() {
+        let zone = |x, y| zone(Stage::BATTLEFIELD, x, y).unwrap();
+        assert_eq!(zone(0.0, 0.0), StageZone::Center);
+        assert_eq!(zone(-60.0, 0.0), StageZone::Ledge);
+        assert_eq!(zone(60.0, 0.0), StageZone::Ledge);
+        assert_eq!(zone(70.0, 0.0), StageZone::Offstage);
+        assert_eq!(zone(0.0, -20.0), StageZone::Offstage);
+        assert_eq!(zone(-40.0, 27.2), StageZone::Platform);
+        assert_eq!(zone(0.0, 54.4), StageZone::Platform);
+    }
+
+    #[test]
+    fn ledge_zone_stops_above_the_ledge() {
+        let zone = |x, y| zone(Stage::FINAL_DESTINATION, x, y).unwrap();
+        assert_eq!(zone(80.0, 30.0), StageZone::Ledge);
+        assert_eq!(zone(80.0, 60.0), StageZone::Center);
+        assert_eq!(zone(-80.0, 60.0), StageZone::Center);
+    }
+
+    #[test]
+    fn dream_land_side_platforms_are_not_mirrored() {
+        let zone = |x, y| zone(Stage::DREAM_LAND_N64, x, y).unwrap();
+        assert_eq!(zone(62.5, 30.24), StageZone::Platform);
+        assert_eq!(zone(-62.5, 30.24), StageZone::Ledge);
+        assert_eq!(zone(-31.72, 30.24), StageZone::Center);
+        assert_eq!(zone(31.72, 30.24), StageZone::Platform);
+    }
+
+    #[test]
+    fn stadium_transformations_arent_platforms() {
+        let zone = |x, y| zone(Stage::POKEMON_STADIUM, x, y).unwrap();
+        assert_eq!(zone(-40.0, 25.0), StageZone::Platform);
+        assert_eq!(zone(0.0, 25.0), StageZone::Center);
+        // On the rock and fire layouts' terrain.
+        assert_eq!(zone(40.0, 14.0), StageZone::Center);
+        assert_eq!(zone(-58.0, 16.0), StageZone::Ledge);
+    }
+
+    #[test]
+    fn no_zones_without_geometry() {
+        assert_eq!(zone(Stage::FOURSIDE, 0.0, 0.0), None);
+    }
+}
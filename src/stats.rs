@@ -0,0 +1,85 @@
+use crate::detector::{Conversion, COUNTER_ATTACK, NEUTRAL_WIN, TRADE};
+use core::fmt::{self, Display};
+use serde::{Deserialize, Serialize};
+
+/// Conversion totals for one player, added up over any number of games.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct ConversionStats {
+    pub games: usize,
+    pub wins: usize,
+    /// Conversions this player started (i.e. openings they got).
+    pub openings: usize,
+    pub kills: usize,
+    pub total_damage: f32,
+    /// Openings by opening type (see `Conversion::opening_type`).
+    pub neutral_wins: usize,
+    pub counter_attacks: usize,
+    pub trades: usize,
+}
+
+impl ConversionStats {
+    pub fn add_conversion(&mut self, conversion: &Conversion) {
+        self.openings += 1;
+        self.total_damage += conversion.damage();
+        if conversion.did_kill {
+            self.kills += 1;
+        }
+        match conversion.opening_type.as_deref() {
+            Some(NEUTRAL_WIN) => self.neutral_wins += 1,
+            Some(COUNTER_ATTACK) => self.counter_attacks += 1,
+            Some(TRADE) => self.trades += 1,
+            _ => (),
+        }
+    }
+
+    pub fn add(&mut self, other: &ConversionStats) {
+        self.games += other.games;
+        self.wins += other.wins;
+        self.openings += other.openings;
+        self.kills += other.kills;
+        self.total_damage += other.total_damage;
+        self.neutral_wins += other.neutral_wins;
+        self.counter_attacks += other.counter_attacks;
+        self.trades += other.trades;
+    }
+
+    pub fn win_rate(&self) -> f32 {
+        match self.games {
+            0 => 0.0,
+            n => self.wins as f32 / n as f32,
+        }
+    }
+
+    pub fn damage_per_opening(&self) -> f32 {
+        match self.openings {
+            0 => 0.0,
+            n => self.total_damage / n as f32,
+        }
+    }
+
+    pub fn openings_per_kill(&self) -> Option<f32> {
+        match self.kills {
+            0 => None,
+            n => Some(self.openings as f32 / n as f32),
+        }
+    }
+}
+
+impl Display for ConversionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let openings_per_kill = match self.openings_per_kill() {
+            Some(n) => format!("{:.2}", n),
+            None => "-".to_string(),
+        };
+        write!(
+            f,
+            "{} wins in {} games, {} openings, {} kills, {:.2} damage per opening, {} openings per kill",
+            self.wins,
+            self.games,
+            self.openings,
+            self.kills,
+            self.damage_per_opening(),
+            openings_per_kill
+        )
+    }
+}
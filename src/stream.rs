@@ -0,0 +1,265 @@
+use crate::detector::ConversionDetector;
+use crate::events::ConversionSink;
+use crate::rollback::{RollbackFilter, RollbackStats};
+use peppi::model::enums::stage::Stage;
+use peppi::model::frame::{self, Data, Frame, PortData, Post, Pre};
+use peppi::model::game::{self, NUM_PORTS};
+use peppi::model::item::Item;
+use peppi::model::primitives::Port;
+use peppi::serde::de::{FrameEvent, FrameId, Handlers, PortId};
+use std::io;
+
+/// Runs conversion detection directly off of parser events, one frame at a time.
+///
+/// Unlike `peppi::game`, this never holds more than the current and previous frame,
+/// so it can follow a replay that is still being written (or streamed) and report
+/// conversions to the sink as soon as they complete.
+pub struct LiveAnalysis<S: ConversionSink> {
+    sink: Option<S>,
+    game: Option<LiveGame<S>>,
+    pending: Option<PendingFrame>,
+    rollback_stats: RollbackStats,
+    last_port_data: [Option<PortData>; NUM_PORTS],
+    ports: Vec<Port>,
+    game_ended: bool,
+}
+
+impl<S: ConversionSink> LiveAnalysis<S> {
+    pub fn new(sink: S) -> Self {
+        LiveAnalysis {
+            sink: Some(sink),
+            game: None,
+            pending: None,
+            rollback_stats: RollbackStats::default(),
+            last_port_data: [(); NUM_PORTS].map(|_| None),
+            ports: Vec::new(),
+            game_ended: false,
+        }
+    }
+
+    /// Whether the game end event has been seen. Anything after it is metadata.
+    pub fn game_ended(&self) -> bool {
+        self.game_ended
+    }
+
+    pub fn rollback_stats(&self) -> &RollbackStats {
+        &self.rollback_stats
+    }
+
+    /// Finishes detection (if the game never ended) and returns the sink.
+    pub fn into_sink(mut self) -> S {
+        match self.game.take() {
+            Some(game) => game.finish(),
+            None => self.sink.take().unwrap(),
+        }
+    }
+
+    /// Returns the frame currently being assembled, first completing the previous one
+    /// if `index` belongs to a new frame.
+    fn frame(&mut self, index: i32) -> &mut PendingFrame {
+        if self.pending.as_ref().is_some_and(|f| f.index != index) {
+            self.flush();
+        }
+        self.pending.get_or_insert_with(|| PendingFrame::new(index))
+    }
+
+    /// Completes the frame being assembled and runs detection on it.
+    fn flush(&mut self) {
+        let pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+        // Eliminated players stop getting frame events, so hold on to their last known data.
+        let mut port_data = Vec::with_capacity(self.ports.len());
+        for port in &self.ports {
+            let last = &mut self.last_port_data[*port as usize];
+            if let Some(data) = pending.port_data(*port) {
+                *last = Some(data);
+            }
+            match last {
+                Some(data) => port_data.push(data.clone()),
+                None => return,
+            }
+        }
+
+        if let Some(game) = &mut self.game {
+            game.push_frame(pending, port_data);
+        }
+    }
+}
+
+impl<S: ConversionSink> Handlers for LiveAnalysis<S> {
+    fn game_start(&mut self, start: game::Start) -> io::Result<()> {
+        self.ports = start.players.iter().map(|p| p.port).collect();
+        let sink = self.sink.take().unwrap();
+        let ports = &self.ports[..];
+        self.game = Some(match ports.len() {
+            1 => LiveGame::P1(Box::new(LiveDetector::new(ports, start.stage, sink))),
+            2 => LiveGame::P2(Box::new(LiveDetector::new(ports, start.stage, sink))),
+            3 => LiveGame::P3(Box::new(LiveDetector::new(ports, start.stage, sink))),
+            4 => LiveGame::P4(Box::new(LiveDetector::new(ports, start.stage, sink))),
+            n => {
+                self.sink = Some(sink);
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unsupported number of ports: {}", n),
+                ));
+            }
+        });
+        Ok(())
+    }
+
+    fn game_end(&mut self, _: game::End) -> io::Result<()> {
+        self.flush();
+        if let Some(game) = self.game.take() {
+            self.sink = Some(game.finish());
+        }
+        self.game_ended = true;
+        Ok(())
+    }
+
+    fn frame_start(&mut self, evt: FrameEvent<FrameId, frame::Start>) -> io::Result<()> {
+        self.rollback_stats.record_frame_start(evt.id.index);
+        self.frame(evt.id.index).start = Some(evt.event);
+        Ok(())
+    }
+
+    fn frame_pre(&mut self, evt: FrameEvent<PortId, Pre>) -> io::Result<()> {
+        let port = evt.id.port as usize;
+        if !evt.id.is_follower {
+            self.rollback_stats.record_pre(evt.id.index);
+        }
+        let pending = self.frame(evt.id.index);
+        match evt.id.is_follower {
+            true => pending.follower_pre[port] = Some(evt.event),
+            _ => pending.leader_pre[port] = Some(evt.event),
+        }
+        Ok(())
+    }
+
+    fn frame_post(&mut self, evt: FrameEvent<PortId, Post>) -> io::Result<()> {
+        let port = evt.id.port as usize;
+        let pending = self.frame(evt.id.index);
+        match evt.id.is_follower {
+            true => pending.follower_post[port] = Some(evt.event),
+            _ => pending.leader_post[port] = Some(evt.event),
+        }
+        Ok(())
+    }
+
+    fn frame_end(&mut self, evt: FrameEvent<FrameId, frame::End>) -> io::Result<()> {
+        self.frame(evt.id.index).end = Some(evt.event);
+        self.flush();
+        Ok(())
+    }
+
+    fn item(&mut self, evt: FrameEvent<FrameId, Item>) -> io::Result<()> {
+        self.frame(evt.id.index).items.push(evt.event);
+        Ok(())
+    }
+}
+
+/// Frame events received so far for a single frame index, keyed by port number.
+struct PendingFrame {
+    index: i32,
+    start: Option<frame::Start>,
+    end: Option<frame::End>,
+    leader_pre: [Option<Pre>; NUM_PORTS],
+    leader_post: [Option<Post>; NUM_PORTS],
+    follower_pre: [Option<Pre>; NUM_PORTS],
+    follower_post: [Option<Post>; NUM_PORTS],
+    items: Vec<Item>,
+}
+
+impl PendingFrame {
+    fn new(index: i32) -> PendingFrame {
+        PendingFrame {
+            index,
+            start: None,
+            end: None,
+            leader_pre: [None; NUM_PORTS],
+            leader_post: [None; NUM_PORTS],
+            follower_pre: [None; NUM_PORTS],
+            follower_post: [None; NUM_PORTS],
+            items: Vec::new(),
+        }
+    }
+
+    fn port_data(&self, port: Port) -> Option<PortData> {
+        let p = port as usize;
+        let leader = Data {
+            pre: self.leader_pre[p]?,
+            post: self.leader_post[p]?,
+        };
+        let follower = match (self.follower_pre[p], self.follower_post[p]) {
+            (Some(pre), Some(post)) => Some(Box::new(Data { pre, post })),
+            _ => None,
+        };
+        Some(PortData { leader, follower })
+    }
+
+    /// `port_data` must have exactly one entry per player.
+    fn into_frame<const N: usize>(self, port_data: Vec<PortData>) -> Frame<N> {
+        Frame {
+            index: self.index,
+            ports: port_data.try_into().unwrap(),
+            start: self.start,
+            end: self.end,
+            items: Some(self.items),
+        }
+    }
+}
+
+/// Same idea as `peppi::model::game::Frames`: the player count is only known once the game starts.
+enum LiveGame<S: ConversionSink> {
+    P1(Box<LiveDetector<1, S>>),
+    P2(Box<LiveDetector<2, S>>),
+    P3(Box<LiveDetector<3, S>>),
+    P4(Box<LiveDetector<4, S>>),
+}
+
+impl<S: ConversionSink> LiveGame<S> {
+    fn push_frame(&mut self, pending: PendingFrame, port_data: Vec<PortData>) {
+        match self {
+            LiveGame::P1(detector) => detector.push_frame(pending.into_frame(port_data)),
+            LiveGame::P2(detector) => detector.push_frame(pending.into_frame(port_data)),
+            LiveGame::P3(detector) => detector.push_frame(pending.into_frame(port_data)),
+            LiveGame::P4(detector) => detector.push_frame(pending.into_frame(port_data)),
+        }
+    }
+
+    fn finish(self) -> S {
+        match self {
+            LiveGame::P1(detector) => detector.finish(),
+            LiveGame::P2(detector) => detector.finish(),
+            LiveGame::P3(detector) => detector.finish(),
+            LiveGame::P4(detector) => detector.finish(),
+        }
+    }
+}
+
+/// Frames may be sent more than once because of rollbacks, so they go through a `RollbackFilter` first.
+struct LiveDetector<const N: usize, S: ConversionSink> {
+    rollbacks: RollbackFilter<N>,
+    detector: ConversionDetector<N, S>,
+}
+
+impl<const N: usize, S: ConversionSink> LiveDetector<N, S> {
+    fn new(ports: &[Port], stage: Stage, sink: S) -> Self {
+        LiveDetector {
+            rollbacks: RollbackFilter::default(),
+            detector: ConversionDetector::new(ports.try_into().unwrap(), stage, sink),
+        }
+    }
+
+    fn push_frame(&mut self, frame: Frame<N>) {
+        let detector = &mut self.detector;
+        self.rollbacks.push(frame, |f| detector.push_frame(f));
+    }
+
+    fn finish(mut self) -> S {
+        let detector = &mut self.detector;
+        self.rollbacks.finish(|f| detector.push_frame(f));
+        self.detector.finish()
+    }
+}
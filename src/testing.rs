@@ -0,0 +1,231 @@
+//! Made-up players, games, frames and replays for tests.
+
+use crate::detector::{Conversion, Location, PlayerAttack};
+use crate::info::{GameInfo, PlayerInfo};
+use crate::library::AnalyzedGame;
+use crate::rollback::RollbackStats;
+use peppi::model::buttons;
+use peppi::model::enums::action_state::{Common, State};
+use peppi::model::enums::attack::Attack;
+use peppi::model::enums::character::{External, Internal};
+use peppi::model::enums::stage::Stage;
+use peppi::model::frame::{Buttons, Data, Frame, PortData, Post, Pre, StateFlags, Triggers};
+use peppi::model::game::{EndMethod, FIRST_FRAME_INDEX};
+use peppi::model::primitives::{Direction, Port, Position};
+use peppi::model::slippi::Version;
+use peppi::model::triggers;
+use std::path::PathBuf;
+
+pub fn player(port: Port, character: External, connect_code: Option<&str>) -> PlayerInfo {
+    PlayerInfo {
+        port,
+        character,
+        costume: 0,
+        tag: None,
+        netplay_name: None,
+        connect_code: connect_code.map(str::to_string),
+        final_stocks: 0,
+        final_percent: 0.0,
+    }
+}
+
+/// A finished game on Battlefield that nobody has won yet.
+pub fn game(players: Vec<PlayerInfo>, conversions: Vec<Conversion>) -> AnalyzedGame {
+    AnalyzedGame {
+        path: PathBuf::from("game.slp"),
+        info: GameInfo {
+            stage: Stage::BATTLEFIELD,
+            players,
+            duration: 3600,
+            end_method: EndMethod::GAME,
+            lras_initiator: None,
+            winners: Vec::new(),
+            slippi_version: Version(3, 12, 0),
+            date: None,
+            platform: None,
+            console: None,
+        },
+        conversions,
+        stocks_lost: Vec::new(),
+        rollbacks: RollbackStats::default(),
+    }
+}
+
+pub fn location(x: f32, y: f32) -> Location {
+    Location { x, y, velocity: None, zone: None }
+}
+
+/// A hit by the player at `player_index`, landing when the defender was at `defender_percent`.
+pub fn attack(player_index: usize, attack: Option<Attack>, defender_percent: f32) -> PlayerAttack {
+    PlayerAttack {
+        player_index: Some(player_index),
+        attack,
+        frame: 0,
+        grab: false,
+        item: None,
+        defender_percent,
+        hitstun: None,
+        attacker_location: None,
+        di: None,
+    }
+}
+
+/// A finished conversion from frame 100 to 200, starting at the first attack's percent.
+pub fn conversion(adv_index: usize, disadv_index: usize, attacks: Vec<PlayerAttack>, end_percent: f32, did_kill: bool) -> Conversion {
+    Conversion {
+        adv_index: Some(adv_index),
+        disadv_index,
+        has_been_grounded_actionable: false,
+        frames_since_last_hit: 0,
+        actionable_frames: 0,
+        start_frame: 100,
+        end_frame: Some(200),
+        start_percent: attacks.first().map_or(0.0, |a| a.defender_percent),
+        end_percent: Some(end_percent),
+        start_location: location(0.0, 0.0),
+        end_location: Some(location(0.0, 0.0)),
+        attacks,
+        did_kill,
+        opening_type: None,
+        follow_up_window: None,
+        missed_follow_up: None,
+    }
+}
+
+/// Fox standing at `x` with 4 stocks and 0%, as recorded from Slippi 2.0 on (with state flags,
+/// without velocities).
+pub fn standing(x: f32) -> PortData {
+    let position = Position { x, y: 0.0 };
+    let pre = Pre {
+        position,
+        direction: Direction::Right,
+        joystick: Position { x: 0.0, y: 0.0 },
+        cstick: Position { x: 0.0, y: 0.0 },
+        triggers: Triggers { logical: 0.0, physical: triggers::Physical { l: 0.0, r: 0.0 } },
+        random_seed: 0,
+        buttons: Buttons { logical: buttons::Logical(0), physical: buttons::Physical(0) },
+        state: State::Common(Common::WAIT),
+        raw_analog_x: None,
+        damage: None,
+    };
+    let post = Post {
+        character: Internal::FOX,
+        state: State::Common(Common::WAIT),
+        position,
+        direction: Direction::Right,
+        damage: 0.0,
+        shield: 60.0,
+        last_attack_landed: None,
+        combo_count: 0,
+        last_hit_by: None,
+        stocks: 4,
+        state_age: None,
+        flags: Some(StateFlags(0)),
+        misc_as: Some(0.0),
+        airborne: Some(false),
+        ground: None,
+        jumps: None,
+        l_cancel: None,
+        hurtbox_state: None,
+        velocities: None,
+        hitlag: None,
+    };
+    PortData { leader: Data { pre, post }, follower: None }
+}
+
+/// Frame `i` counted from the first frame, like `Conversion::start_frame`.
+pub fn frame<const N: usize>(i: usize, ports: [PortData; N]) -> Frame<N> {
+    Frame { index: i as i32 + FIRST_FRAME_INDEX, ports, start: None, end: None, items: None }
+}
+
+/// What one player is doing on one frame of a made-up replay.
+#[derive(Clone, Copy, Debug)]
+pub struct ReplayPost {
+    pub state: Common,
+    pub x: f32,
+    pub percent: f32,
+    /// Index of the player who last hit them.
+    pub last_hit_by: Option<u8>,
+    pub last_attack_landed: Option<Attack>,
+    pub stocks: u8,
+}
+
+impl ReplayPost {
+    pub fn standing(x: f32) -> ReplayPost {
+        ReplayPost { state: Common::WAIT, x, percent: 0.0, last_hit_by: None, last_attack_landed: None, stocks: 4 }
+    }
+}
+
+/// A minimal Slippi 0.1 replay of two Foxes on Battlefield, with `frames` frames from the first,
+/// where `post(i, port)` says what each of them is doing on frame `i`.
+pub fn replay(frames: usize, post: impl Fn(usize, usize) -> ReplayPost) -> Vec<u8> {
+    let (start_size, pre_size, post_size) = (320, 58, 33);
+    let mut raw = vec![0x35, 13];
+    for (code, size) in [(0x36, start_size), (0x37, pre_size), (0x38, post_size), (0x39, 1)] {
+        raw.push(code);
+        raw.extend((size as u16).to_be_bytes());
+    }
+
+    let mut start = vec![0; start_size];
+    // Version 0.1.0, on Battlefield.
+    start[..4].copy_from_slice(&[0, 1, 0, 0]);
+    start[18..20].copy_from_slice(&31u16.to_be_bytes());
+    for port in 0..6 {
+        // Fox with 4 stocks in the first two slots, nobody in the others.
+        let player = 100 + port * 36;
+        start[player..player + 3].copy_from_slice(&[2, if port < 2 { 0 } else { 3 }, 4]);
+    }
+    raw.push(0x36);
+    raw.extend(start);
+
+    for i in 0..frames {
+        let index = i as i32 + FIRST_FRAME_INDEX;
+        for (code, size) in [(0x37, pre_size), (0x38, post_size)] {
+            for port in 0..2 {
+                let post = post(i, port);
+                let mut event = vec![0; size];
+                event[..4].copy_from_slice(&index.to_be_bytes());
+                event[4] = port as u8;
+                if code == 0x37 {
+                    // Facing right.
+                    event[20..24].copy_from_slice(&1.0f32.to_be_bytes());
+                } else {
+                    event[6] = Internal::FOX.0;
+                    event[7..9].copy_from_slice(&post.state.0.to_be_bytes());
+                    event[9..13].copy_from_slice(&post.x.to_be_bytes());
+                    event[17..21].copy_from_slice(&1.0f32.to_be_bytes());
+                    event[21..25].copy_from_slice(&post.percent.to_be_bytes());
+                    event[29] = post.last_attack_landed.map_or(0, |a| a.0);
+                    event[31] = post.last_hit_by.unwrap_or(0xff);
+                    event[32] = post.stocks;
+                }
+                raw.push(code);
+                raw.extend(event);
+            }
+        }
+    }
+    raw.extend([0x39, 2]);
+
+    let mut replay = vec![0x7b, 0x55, 0x03, 0x72, 0x61, 0x77, 0x5b, 0x24, 0x55, 0x23, 0x6c];
+    replay.extend((raw.len() as u32).to_be_bytes());
+    replay.extend(raw);
+    replay.extend(b"U\x08metadata{}}");
+    replay
+}
+
+/// A replay where the first Fox (at x = -10) forward airs the other (at x = 10) to 12% on frame
+/// 200, leaving them in damage for 20 frames.
+pub fn fair_replay(frames: usize) -> Vec<u8> {
+    replay(frames, |i, port| match port {
+        0 => ReplayPost {
+            last_attack_landed: (i >= 200).then_some(Attack::FAIR),
+            ..ReplayPost::standing(-10.0)
+        },
+        _ => ReplayPost {
+            state: if (200..220).contains(&i) { Common::DAMAGE_HI_1 } else { Common::WAIT },
+            percent: if i >= 200 { 12.0 } else { 0.0 },
+            last_hit_by: (i >= 200).then_some(0),
+            ..ReplayPost::standing(10.0)
+        },
+    })
+}
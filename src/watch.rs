@@ -0,0 +1,163 @@
+use crate::events::PrintSink;
+use crate::stream::LiveAnalysis;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Slippi stops writing while the game is paused, so this needs to be fairly generous.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Reads a file that is still being written, waiting for more data instead of stopping at the end.
+///
+/// Only gives up (by returning end of file) after nothing has been appended for `idle_timeout`.
+pub struct TailReader {
+    file: File,
+    poll_interval: Duration,
+    idle_timeout: Duration,
+}
+
+impl TailReader {
+    pub fn new(file: File) -> TailReader {
+        TailReader {
+            file,
+            poll_interval: POLL_INTERVAL,
+            idle_timeout: IDLE_TIMEOUT,
+        }
+    }
+}
+
+impl Read for TailReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let idle_since = Instant::now();
+        loop {
+            let read = self.file.read(buf)?;
+            if read > 0 || buf.is_empty() || idle_since.elapsed() >= self.idle_timeout {
+                return Ok(read);
+            }
+            thread::sleep(self.poll_interval);
+        }
+    }
+}
+
+/// Follows a replay as Slippi writes it, printing conversions as soon as they complete.
+///
+/// `path` may be a single replay, or a directory in which case every replay that Slippi
+/// starts writing there is followed in turn.
+pub fn watch(path: &Path) {
+    if path.is_dir() {
+        watch_dir(path);
+    } else {
+        watch_file(path);
+    }
+}
+
+fn watch_dir(dir: &Path) {
+    let since = SystemTime::now();
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    println!("Waiting for new replays in {}", dir.display());
+    loop {
+        match newest_replay(dir, since, &seen) {
+            Some(replay) => {
+                watch_file(&replay);
+                seen.insert(replay);
+            }
+            None => thread::sleep(Duration::from_secs(1)),
+        }
+    }
+}
+
+/// The most recently modified `.slp` in `dir` that was touched after `since` and hasn't been watched yet.
+fn newest_replay(dir: &Path, since: SystemTime, seen: &HashSet<PathBuf>) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "slp") && !seen.contains(path))
+        .filter_map(|path| {
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+            (modified >= since).then_some((modified, path))
+        })
+        .max()
+        .map(|(_, path)| path)
+}
+
+fn watch_file(path: &Path) {
+    let init_time = Instant::now();
+    println!("Watching {}", path.display());
+
+    let mut reader = io::BufReader::new(TailReader::new(File::open(path).unwrap()));
+    let mut analysis = LiveAnalysis::new(PrintSink::default());
+    let result = peppi::parse(&mut reader, &mut analysis, None);
+
+    // Once the game has ended, conversions have all been reported and only the metadata is left.
+    match result {
+        Err(e) if !analysis.game_ended() => eprintln!("Stopped watching {}: {}", path.display(), e),
+        _ => println!("{:#?}", init_time.elapsed()),
+    }
+    if analysis.rollback_stats().rollbacks > 0 {
+        println!("{}", analysis.rollback_stats());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::env;
+    use std::io::Write;
+    use std::process;
+
+    fn tail_reader(file: File) -> TailReader {
+        TailReader {
+            file,
+            poll_interval: Duration::from_millis(5),
+            idle_timeout: Duration::from_secs(5),
+        }
+    }
+
+    #[test]
+    fn parses_a_replay_written_in_chunks() {
+        let replay = testing::fair_replay(600);
+        let path = env::temp_dir().join(format!("slipnsights-watch-{}.slp", process::id()));
+        let mut file = File::create(&path).unwrap();
+        let reader = tail_reader(File::open(&path).unwrap());
+
+        let writer = thread::spawn(move || {
+            for chunk in replay.chunks(1000) {
+                file.write_all(chunk).unwrap();
+                file.flush().unwrap();
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+
+        let mut analysis = LiveAnalysis::new(Vec::new());
+        let result = peppi::parse(&mut io::BufReader::new(reader), &mut analysis, None);
+        writer.join().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_ok(), "{:?}", result.err());
+        assert!(analysis.game_ended());
+        let conversions = analysis.into_sink();
+        assert_eq!(conversions.len(), 1);
+        assert_eq!(conversions[0].attacks[0].name(), "FAIR");
+    }
+
+    #[test]
+    fn gives_up_once_idle() {
+        let path = env::temp_dir().join(format!("slipnsights-watch-idle-{}.slp", process::id()));
+        File::create(&path).unwrap().write_all(b"{").unwrap();
+        let mut reader = tail_reader(File::open(&path).unwrap());
+        reader.idle_timeout = Duration::from_millis(50);
+
+        let mut buf = [0; 8];
+        assert_eq!(reader.read(&mut buf).unwrap(), 1);
+        let waiting = Instant::now();
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+        assert!(waiting.elapsed() >= Duration::from_millis(50));
+        fs::remove_file(&path).unwrap();
+    }
+}